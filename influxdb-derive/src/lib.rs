@@ -1,131 +1,298 @@
 #![recursion_limit = "128"]
 
 extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
 extern crate syn;
 #[macro_use]
 extern crate quote;
 extern crate itertools;
 
 use proc_macro::TokenStream;
-use syn::Ident;
+use proc_macro2::{Span, TokenStream as Tokens};
+use quote::ToTokens;
+use syn::{Error, Ident, Result};
 use itertools::Itertools;
 
 // TODO: documentation
 
 #[proc_macro_derive(Measurement, attributes(influx))]
 pub fn derive_measurement(input: TokenStream) -> TokenStream {
-    // Construct a string representation of the type definition
-    let s = input.to_string();
-
-    // Parse the string representation
-    let ast = syn::parse_macro_input(&s).unwrap();
+    // Parse the type definition
+    let ast = parse_macro_input!(input as syn::DeriveInput);
 
-    // Build the impl
-    let gen = impl_measurement(&ast);
+    // Build the impl, or the compile errors explaining why we can't
+    let gen = impl_measurement(&ast).unwrap_or_else(|e| e.to_compile_error());
 
     // Return the generated impl
-    gen.parse().unwrap()
+    gen.into()
 }
 
-fn impl_measurement(input: &syn::DeriveInput) -> quote::Tokens {
-    let struct_fields = parse_influx_struct_fields(input);
+fn impl_measurement(input: &syn::DeriveInput) -> Result<Tokens> {
+    let struct_fields = parse_influx_struct_fields(input)?;
 
     let name = &input.ident;
 
-    let struct_attr = parse_influx_db_attrs(&input.attrs);
+    let struct_attr = parse_influx_db_attrs(&input.attrs)?;
+    if let Some(ref attr) = struct_attr {
+        if let Some(span) = attr.kind_span() {
+            return Err(Error::new(span, "`tag`, `field` and `timestamp` are only valid on struct fields"));
+        }
+    }
+    if let Some(ref attr) = struct_attr {
+        check_name(attr)?;
+    }
     let measurement_name = struct_attr.and_then(|a| a.name).unwrap_or_else(|| input.ident.to_string());
+    // The name is known now, so it can be escaped once instead of on
+    // every serialization
+    let escaped_measurement_name = measurement_name.chars()
+        .fold(String::new(), |mut escaped, c| {
+            if c == ',' || c == ' ' { escaped.push('\\') }
+            escaped.push(c);
+            escaped
+        });
 
     let tags: Vec<_> = struct_fields.iter().filter(|f| f.is_tag()).collect();
     let fields: Vec<_> = struct_fields.iter().filter(|f| f.is_field()).collect();
     let timestamps: Vec<_> = struct_fields.iter().filter(|f| f.is_timestamp()).collect();
 
     if fields.is_empty() {
-        panic!("InfluxDB requires that a measurement has at least one field");
+        return Err(Error::new(name.span(), "InfluxDB requires that a measurement has at least one field; \
+                                           mark one with `#[influx(field)]`"));
     }
 
-    if timestamps.len() > 1 {
-        panic!("InfluxDB requires a maximum of one timestamp per measurement");
+    if let Some(extra) = timestamps.get(1) {
+        return Err(Error::new(extra.field_name.span(),
+                              "InfluxDB requires a maximum of one timestamp per measurement"));
     }
 
-    let name_and_tag_separator = if tags.is_empty() {
-        quote!{ }
-    } else {
-        quote!{ v.push_str(","); }
-    };
+    check_unique_names(&tags)?;
+    check_unique_names(&fields)?;
 
     let tag_stmts = tags.iter()
         .map(|field| {
             let field_name = field.field_name();
             let name = field.name();
+            if field.is_optional() {
+                quote!{
+                    if let Some(ref value) = self.#field_name {
+                        v.push(b',');
+                        influxdb::measurement::Tag::new(#name, value).append_bytes(v)?;
+                    }
+                }
+            } else {
+                quote!{
+                    v.push(b',');
+                    influxdb::measurement::Tag::new(#name, &self.#field_name).append_bytes(v)?;
+                }
+            }
+        });
+
+    let field_stmts = if fields.iter().any(|f| f.is_optional()) {
+        optional_field_stmts(&fields, &measurement_name)
+    } else {
+        required_field_stmts(&fields)
+    };
+
+    let timestamp_stmts = timestamps.iter()
+        .map(|field| {
+            let field_name = field.field_name();
             quote!{
-                influxdb::measurement::Tag::new(#name, &self.#field_name).append(v);
+                influxdb::measurement::Timestamp::new(&self.#field_name).append_bytes(v)?;
             }
-        })
-        .intersperse(quote!{ v.push_str(","); });
+        });
+
+    let generics = add_value_bounds(&input.generics, &tags, &fields);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote!{
+        impl #impl_generics influxdb::Measurement for #name #ty_generics #where_clause {
+            fn to_data(&self, v: &mut String) {
+                if let Err(e) = self.try_to_data(v) {
+                    panic!("Unable to serialize {}: {}", #measurement_name, e);
+                }
+            }
+
+            fn try_to_data(&self, v: &mut String) -> ::std::result::Result<(), influxdb::Error> {
+                let mut bytes = Vec::new();
+                self.to_bytes(&mut bytes)?;
+                v.push_str(::std::str::from_utf8(&bytes)?);
+                Ok(())
+            }
+
+            fn to_bytes(&self, v: &mut Vec<u8>) -> ::std::result::Result<(), influxdb::Error> {
+                v.extend_from_slice(#escaped_measurement_name.as_bytes());
+                #(#tag_stmts)*
+
+                v.push(b' ');
 
+                #field_stmts
+
+                v.push(b' ');
+
+                #(#timestamp_stmts)*
+
+                Ok(())
+            }
+        }
+    })
+}
+
+/// When every field is always present, the separators between them
+/// can be decided at compile time.
+fn required_field_stmts(fields: &[&InfluxStructField]) -> Tokens {
     let field_stmts = fields.iter()
         .map(|field| {
             let field_name = field.field_name();
             let name = field.name();
             quote!{
-                influxdb::measurement::Field::new(#name, &self.#field_name).append(v);
+                influxdb::measurement::Field::new(#name, &self.#field_name).append_bytes(v)?;
             }
         })
-        .intersperse(quote!{ v.push_str(","); });
+        .intersperse(quote!{ v.push(b','); });
 
-    let timestamp_stmts = timestamps.iter()
+    quote!{ #(#field_stmts)* }
+}
+
+/// Optional fields are skipped when `None`, so whether a separator is
+/// needed can only be known at runtime.
+fn optional_field_stmts(fields: &[&InfluxStructField], measurement_name: &str) -> Tokens {
+    let field_stmts = fields.iter()
         .map(|field| {
             let field_name = field.field_name();
-            quote!{
-                influxdb::measurement::Timestamp::new(&self.#field_name).append(v);
+            let name = field.name();
+            if field.is_optional() {
+                quote!{
+                    if let Some(ref value) = self.#field_name {
+                        if has_fields { v.push(b','); }
+                        influxdb::measurement::Field::new(#name, value).append_bytes(v)?;
+                        has_fields = true;
+                    }
+                }
+            } else {
+                quote!{
+                    if has_fields { v.push(b','); }
+                    influxdb::measurement::Field::new(#name, &self.#field_name).append_bytes(v)?;
+                    has_fields = true;
+                }
             }
         });
 
     quote!{
-        impl influxdb::Measurement for #name {
-            fn to_data(&self, v: &mut String) {
-                v.push_str(#measurement_name);
-                #name_and_tag_separator;
-                #(#tag_stmts)*
+        let mut has_fields = false;
+        #(#field_stmts)*
+        if !has_fields {
+            return Err(influxdb::Error::NoFields(String::from(#measurement_name)));
+        }
+    }
+}
 
-                v.push_str(" ");
+/// Generic tags and fields can only be serialized when their types
+/// are `TagValue`s and `FieldValue`s, so require that of every type
+/// that mentions one of the struct's type parameters.
+fn add_value_bounds(generics: &syn::Generics, tags: &[&InfluxStructField], fields: &[&InfluxStructField]) -> syn::Generics {
+    let type_params: Vec<_> = generics.type_params().map(|param| param.ident.to_string()).collect();
+    let mut generics = generics.clone();
 
-                #(#field_stmts)*
+    if type_params.is_empty() {
+        return generics;
+    }
 
-                v.push_str(" ");
+    {
+        let where_clause = generics.make_where_clause();
+        let bounded = tags.iter().map(|tag| (tag, quote!{ influxdb::measurement::TagValue }))
+            .chain(fields.iter().map(|field| (field, quote!{ influxdb::measurement::FieldValue })));
 
-                #(#timestamp_stmts)*
+        for (field, bound) in bounded {
+            let ty = unwrap_option(&field.ty);
+            if mentions_any(ty.into_token_stream(), &type_params) {
+                where_clause.predicates.push(parse_quote!{ #ty: #bound });
             }
         }
     }
+
+    generics
 }
 
-fn parse_influx_struct_fields(input: &syn::DeriveInput) -> Vec<InfluxStructField> {
-    use syn::{Body, VariantData};
+fn mentions_any(tokens: Tokens, idents: &[String]) -> bool {
+    use proc_macro2::TokenTree;
 
-    let struct_fields = match input.body {
-        Body::Struct(VariantData::Struct(ref fields)) => fields,
-        _ => panic!("derive(Measurement) is only valid for structs"),
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(ref ident) => idents.iter().any(|i| ident == i),
+        TokenTree::Group(ref group) => mentions_any(group.stream(), idents),
+        _ => false,
+    })
+}
+
+/// The line protocol has no way to escape line breaks
+fn check_name(attr: &InfluxAttr) -> Result<()> {
+    match (&attr.name, attr.name_span) {
+        (&Some(ref name), Some(span)) if name.contains(|c| c == '\n' || c == '\r') => {
+            Err(Error::new(span, "InfluxDB names cannot contain line breaks"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// InfluxDB would silently keep only one of two tags (or two fields)
+/// sharing a name, so reject that at compile time instead.
+fn check_unique_names(fields: &[&InfluxStructField]) -> Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        let name = field.name();
+        if let Some(original) = fields[..i].iter().find(|f| f.name() == name) {
+            let message = format!("the name `{}` is already used by `{}`", name, original.field_name);
+            return Err(Error::new(field.name_span(), message));
+        }
+    }
+    Ok(())
+}
+
+fn parse_influx_struct_fields(input: &syn::DeriveInput) -> Result<Vec<InfluxStructField>> {
+    use syn::{Data, Fields};
+
+    let struct_fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            Fields::Unnamed(ref fields) => {
+                return Err(Error::new_spanned(fields, "derive(Measurement) requires a struct with named fields"));
+            }
+            Fields::Unit => {
+                return Err(Error::new(input.ident.span(), "derive(Measurement) requires a struct with named fields"));
+            }
+        },
+        Data::Enum(ref data) => {
+            return Err(Error::new(data.enum_token.span, "derive(Measurement) is only valid for structs"));
+        }
+        Data::Union(ref data) => {
+            return Err(Error::new(data.union_token.span, "derive(Measurement) is only valid for structs"));
+        }
     };
 
-    struct_fields.iter().filter_map(|field| {
-        parse_influx_db_attrs(&field.attrs).map(|attr| {
-            let field_name = field.ident.clone().expect("All fields must be named");
-            InfluxStructField::new(field_name, attr)
-        })
-    }).collect()
+    let mut influx_fields = Vec::new();
+
+    for field in struct_fields {
+        if let Some(attr) = parse_influx_db_attrs(&field.attrs)? {
+            let field_name = field.ident.clone().expect("Named fields always have a name");
+            let influx_field = InfluxStructField::new(field_name, field.ty.clone(), attr);
+            influx_field.check()?;
+            influx_fields.push(influx_field);
+        }
+    }
+
+    Ok(influx_fields)
 }
 
-#[derive(Debug)]
 struct InfluxStructField {
     field_name: Ident,
+    ty: syn::Type,
     attr: InfluxAttr,
 }
 
 impl InfluxStructField {
-    fn new(field_name: Ident, attr: InfluxAttr) -> Self {
+    fn new(field_name: Ident, ty: syn::Type, attr: InfluxAttr) -> Self {
         InfluxStructField {
             field_name: field_name,
+            ty: ty,
             attr: attr,
         }
     }
@@ -138,77 +305,174 @@ impl InfluxStructField {
         self.attr.name.clone().unwrap_or_else(|| self.field_name.to_string())
     }
 
-    fn is_tag(&self) -> bool { self.attr.is_tag }
-    fn is_field(&self) -> bool { self.attr.is_field }
-    fn is_timestamp(&self) -> bool { self.attr.is_timestamp }
+    /// Where the name sent to InfluxDB was written: the `rename`
+    /// value if there is one, otherwise the field itself.
+    fn name_span(&self) -> Span {
+        self.attr.name_span.unwrap_or_else(|| self.field_name.span())
+    }
+
+    fn is_tag(&self) -> bool { self.attr.tag.is_some() }
+    fn is_field(&self) -> bool { self.attr.field.is_some() }
+    fn is_timestamp(&self) -> bool { self.attr.timestamp.is_some() }
+    fn is_optional(&self) -> bool { is_option(&self.ty) }
+
+    fn check(&self) -> Result<()> {
+        let attr = &self.attr;
+        let ty = &self.ty;
+
+        check_name(attr)?;
+
+        if let (Some(_), Some(field)) = (attr.tag, attr.field) {
+            let message = format!("`{}` cannot be both a tag and a field", self.field_name);
+            return Err(Error::new(field, message));
+        }
+
+        if let Some(timestamp) = attr.timestamp {
+            if attr.tag.is_some() || attr.field.is_some() {
+                let message = format!("`{}` cannot be both a timestamp and a tag or field", self.field_name);
+                return Err(Error::new(timestamp, message));
+            }
+            if attr.name.is_some() {
+                return Err(Error::new(self.name_span(), "a timestamp cannot be renamed"));
+            }
+        }
+
+        if self.is_timestamp() && self.is_optional() {
+            return Err(Error::new_spanned(ty, "the timestamp cannot be an `Option`"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Detects `Option<T>`, however the path to it is spelled
+/// (`Option`, `std::option::Option`, `::std::option::Option`).
+fn is_option(ty: &syn::Type) -> bool {
+    option_inner(ty).is_some()
+}
+
+fn unwrap_option(ty: &syn::Type) -> &syn::Type {
+    option_inner(ty).unwrap_or(ty)
+}
+
+fn option_inner(ty: &syn::Type) -> Option<&syn::Type> {
+    use syn::{Type, PathArguments, GenericArgument};
+
+    let path = match *ty {
+        Type::Path(ref path) if path.qself.is_none() => &path.path,
+        _ => return None,
+    };
+
+    let segment = match path.segments.last() {
+        Some(segment) => segment.into_value(),
+        None => return None,
+    };
+
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref data) if data.args.len() == 1 => {
+            match data.args[0] {
+                GenericArgument::Type(ref inner) => Some(inner),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
 }
 
 #[derive(Debug, Default)]
 struct InfluxAttr {
     name: Option<String>,
-    is_tag: bool,
-    is_field: bool,
-    is_timestamp: bool,
+    name_span: Option<Span>,
+    tag: Option<Span>,
+    field: Option<Span>,
+    timestamp: Option<Span>,
 }
 
 impl InfluxAttr {
     fn merge(self, other: Self) -> Self {
         InfluxAttr {
             name: other.name.or(self.name),
-            is_tag: other.is_tag || self.is_tag,
-            is_field: other.is_field || self.is_field,
-            is_timestamp: other.is_timestamp || self.is_timestamp,
+            name_span: other.name_span.or(self.name_span),
+            tag: other.tag.or(self.tag),
+            field: other.field.or(self.field),
+            timestamp: other.timestamp.or(self.timestamp),
         }
     }
+
+    /// The first `tag`, `field` or `timestamp` marker, if any.
+    fn kind_span(&self) -> Option<Span> {
+        self.tag.or(self.field).or(self.timestamp)
+    }
 }
 
-fn parse_influx_db_attrs(attrs: &[syn::Attribute]) -> Option<InfluxAttr> {
-    use syn::MetaItem;
+fn parse_influx_db_attrs(attrs: &[syn::Attribute]) -> Result<Option<InfluxAttr>> {
+    use syn::Meta;
 
-    attrs.iter().filter_map(|attr| {
-        match attr.value {
-            MetaItem::List(ref ident, ref items) => {
-                // #[influx(...)]
-                if ident == "influx" {
-                    Some(parse_influx_db_attr(items))
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        }
-    }).fold(None, |acc, attr| {
-        match acc {
-            Some(old_attr) => Some(old_attr.merge(attr)),
-            None => Some(attr),
+    let mut influx_attr = None;
+
+    for attr in attrs {
+        if !attr.path.is_ident("influx") {
+            continue;
         }
-    })
+
+        // #[influx(...)]
+        let items = match attr.parse_meta()? {
+            Meta::List(list) => list.nested,
+            meta => return Err(Error::new_spanned(meta, "expected `#[influx(...)]`")),
+        };
+
+        let attr = parse_influx_db_attr(&items)?;
+        influx_attr = Some(match influx_attr {
+            Some(old_attr) => InfluxAttr::merge(old_attr, attr),
+            None => attr,
+        });
+    }
+
+    Ok(influx_attr)
 }
 
-fn parse_influx_db_attr(items: &[syn::NestedMetaItem]) -> InfluxAttr {
-    use syn::{MetaItem, NestedMetaItem, Lit, StrStyle};
+fn parse_influx_db_attr<'a, I>(items: I) -> Result<InfluxAttr>
+    where I: IntoIterator<Item = &'a syn::NestedMeta>,
+{
+    use syn::{Meta, NestedMeta, Lit};
 
     let mut influx_attr = InfluxAttr::default();
 
     for item in items {
         match *item {
-            NestedMetaItem::MetaItem(MetaItem::Word(ref ident)) => {
+            NestedMeta::Meta(Meta::Word(ref ident)) => {
                 // #[influx(tag)]
-                if ident == "tag" { influx_attr.is_tag = true }
+                if ident == "tag" { influx_attr.tag = Some(ident.span()) }
                 // #[influx(field)]
-                if ident == "field" { influx_attr.is_field = true }
+                else if ident == "field" { influx_attr.field = Some(ident.span()) }
                 // #[influx(timestamp)]
-                if ident == "timestamp" { influx_attr.is_timestamp = true }
+                else if ident == "timestamp" { influx_attr.timestamp = Some(ident.span()) }
+                else {
+                    let message = format!("unknown `influx` attribute `{}`; \
+                                           expected `tag`, `field`, `timestamp` or `rename`", ident);
+                    return Err(Error::new(ident.span(), message));
+                }
             }
-            NestedMetaItem::MetaItem(MetaItem::NameValue(ref name, Lit::Str(ref value, StrStyle::Cooked))) => {
+            NestedMeta::Meta(Meta::NameValue(ref name_value)) if name_value.ident == "rename" => {
                 // #[influx(rename = "new_name")]
-                if name == "rename" {
-                    influx_attr.name = Some(value.to_string());
+                match name_value.lit {
+                    Lit::Str(ref value) => {
+                        influx_attr.name = Some(value.value());
+                        influx_attr.name_span = Some(value.span());
+                    }
+                    ref lit => return Err(Error::new_spanned(lit, "`rename` expects a string")),
                 }
             }
-            _ => panic!("Unknown `influx` attribute found"),
+            ref item => {
+                return Err(Error::new_spanned(item, "unknown `influx` attribute; \
+                                                    expected `tag`, `field`, `timestamp` or `rename`"));
+            }
         }
     }
 
-    influx_attr
+    Ok(influx_attr)
 }
@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+struct DuplicateName {
+    #[influx(field)]
+    value: f64,
+    #[influx(field, rename = "value")]
+    other_value: f64,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+enum NotAStruct {
+    One,
+    Two,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+use std::time::SystemTime;
+
+#[derive(Measurement)]
+struct MultipleTimestamps {
+    #[influx(field)]
+    value: f64,
+    #[influx(timestamp)]
+    created: SystemTime,
+    #[influx(timestamp)]
+    updated: SystemTime,
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+struct NoFields {
+    #[influx(tag)]
+    host: String,
+}
+
+fn main() {}
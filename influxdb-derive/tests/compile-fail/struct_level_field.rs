@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+#[influx(field)]
+struct StructLevelField {
+    #[influx(field)]
+    value: f64,
+}
+
+fn main() {}
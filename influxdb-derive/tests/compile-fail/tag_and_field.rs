@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+struct TagAndField {
+    #[influx(tag, field)]
+    region: String,
+    #[influx(field)]
+    value: f64,
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+struct Tuple(#[influx(field)] f64);
+
+fn main() {}
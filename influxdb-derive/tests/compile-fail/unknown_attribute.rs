@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate influxdb_derive;
+extern crate influxdb;
+
+#[derive(Measurement)]
+struct UnknownAttribute {
+    #[influx(field, tga)]
+    value: f64,
+}
+
+fn main() {}
@@ -0,0 +1,220 @@
+//! A synchronous client for scripts and command line tools that don't
+//! want to drive a `tokio_core` reactor themselves.
+//!
+//! Enabled by the `blocking` feature.
+//!
+//! ```no_run
+//! extern crate influxdb;
+//!
+//! use influxdb::blocking::Db;
+//!
+//! fn main() {
+//!     let db = Db::new("http://localhost:8086/", "my_database")
+//!         .expect("Unable to create Db");
+//!
+//!     db.create_database().expect("Unable to create the database");
+//!     db.add_data("cpu,host=server01 value=0.64").expect("Unable to add data");
+//!
+//!     let response = db.query("SELECT * FROM cpu").expect("Unable to query");
+//!     println!("{:?}", response);
+//! }
+//! ```
+
+use std::cell::RefCell;
+use std::time::SystemTime;
+
+use futures::{Future, Stream};
+use tokio_core::reactor::Core;
+
+use flux::{FluxQuery, FluxRecord};
+use sql::SqlQuery;
+use v2::{Authorization, Bucket, DbrpMapping, NewAuthorization, NewBucket, NewDbrpMapping, Organization, Page,
+         Predicate, Status};
+use version::{ServerVersion, WriteApi};
+use {AsyncDb, Error, Measurement, Precision, QueryResponse, Result, Series, SeriesFilter};
+
+/// Sends data to and queries InfluxDB's HTTP API, waiting for each
+/// request to complete.
+///
+/// Each `Db` runs its own reactor core on the calling thread.
+pub struct Db {
+    core: RefCell<Core>,
+    db: AsyncDb,
+}
+
+impl Db {
+    pub fn new(base_url: &str, name: &str) -> Result<Self> {
+        let core = Core::new().map_err(Error::Reactor)?;
+        let db = AsyncDb::new(core.handle(), base_url, name)?;
+
+        Ok(Db {
+            core: RefCell::new(core),
+            db: db,
+        })
+    }
+
+    /// Writes to `bucket` in InfluxDB 2.x; see `AsyncDb::v2`
+    pub fn v2(base_url: &str, org: &str, bucket: &str, token: &str) -> Result<Self> {
+        let core = Core::new().map_err(Error::Reactor)?;
+        let db = AsyncDb::v2(core.handle(), base_url, org, bucket, token)?;
+
+        Ok(Db {
+            core: RefCell::new(core),
+            db: db,
+        })
+    }
+
+    /// See `AsyncDb::set_epoch`
+    pub fn set_epoch(&mut self, epoch: Option<Precision>) {
+        self.db.set_epoch(epoch);
+    }
+
+    /// See `AsyncDb::set_token`
+    pub fn set_token(&mut self, token: Option<&str>) {
+        self.db.set_token(token);
+    }
+
+    pub fn add_data<T>(&self, measure: T) -> Result<()>
+        where T: Measurement
+    {
+        self.run(self.db.add_data(measure))
+    }
+
+    pub fn query(&self, query: &str) -> Result<QueryResponse> {
+        self.run(self.db.query(query))
+    }
+
+    /// Runs a query by POSTing it in a form-encoded body
+    pub fn query_post(&self, query: &str) -> Result<QueryResponse> {
+        self.run(self.db.query_post(query))
+    }
+
+    /// Sends several statements in one request; see
+    /// `AsyncDb::query_batch`.
+    pub fn query_batch<I>(&self, statements: I) -> Result<Vec<Result<Vec<Series>>>>
+        where I: IntoIterator,
+              I::Item: AsRef<str>,
+    {
+        self.run(self.db.query_batch(statements))
+    }
+
+    /// Runs a Flux query and collects every row of the results
+    pub fn flux_query(&self, query: &FluxQuery) -> Result<Vec<FluxRecord>> {
+        self.run(self.db.flux_query(query).collect())
+    }
+
+    /// Creates the database. Succeeds if it already exists, and fails
+    /// for InfluxDB 2.x.
+    pub fn create_database(&self) -> Result<()> {
+        self.run(self.db.create_database())
+    }
+
+    /// Drops the database and all of its data. Succeeds if it doesn't
+    /// exist, and fails for InfluxDB 2.x.
+    pub fn drop_database(&self) -> Result<()> {
+        self.run(self.db.drop_database())
+    }
+
+    /// See `AsyncDb::delete`
+    pub fn delete(&self, filter: &SeriesFilter) -> Result<()> {
+        self.run(self.db.delete(filter))
+    }
+
+    /// See `AsyncDb::drop_series`
+    pub fn drop_series(&self, filter: &SeriesFilter) -> Result<()> {
+        self.run(self.db.drop_series(filter))
+    }
+
+    /// Deletes a measurement, its series, and all of its points
+    pub fn drop_measurement(&self, measurement: &str) -> Result<()> {
+        self.run(self.db.drop_measurement(measurement))
+    }
+
+    /// Deletes a shard, as listed by `SHOW SHARDS`, and its points
+    pub fn drop_shard(&self, id: u64) -> Result<()> {
+        self.run(self.db.drop_shard(id))
+    }
+
+    /// Runs a SQL query and joins its rows into `Series`; see
+    /// `SqlRows::series`
+    pub fn sql_query(&self, query: &SqlQuery) -> Result<Vec<Series>> {
+        self.run(self.db.sql_query(query).series())
+    }
+
+    /// Asks the server which InfluxDB it is
+    pub fn detect_version(&self) -> Result<ServerVersion> {
+        self.run(self.db.detect_version())
+    }
+
+    /// See `AsyncDb::set_write_api`
+    pub fn set_write_api(&mut self, api: WriteApi) -> Result<()> {
+        self.db.set_write_api(api)
+    }
+
+    /// See `AsyncDb::delete_range`
+    pub fn delete_range(&self, start: SystemTime, stop: SystemTime, predicate: &Predicate) -> Result<()> {
+        self.run(self.db.delete_range(start, stop, predicate))
+    }
+
+    /// See `AsyncDb::buckets`
+    pub fn buckets(&self, page: Page) -> Result<Vec<Bucket>> {
+        self.run(self.db.buckets(page))
+    }
+
+    pub fn create_bucket(&self, bucket: &NewBucket) -> Result<Bucket> {
+        self.run(self.db.create_bucket(bucket))
+    }
+
+    /// Deletes a bucket and all of its data
+    pub fn delete_bucket(&self, id: &str) -> Result<()> {
+        self.run(self.db.delete_bucket(id))
+    }
+
+    /// Lists the organizations the token can read
+    pub fn orgs(&self, page: Page) -> Result<Vec<Organization>> {
+        self.run(self.db.orgs(page))
+    }
+
+    /// Finds the ID of the organization called `name`
+    pub fn org_id(&self, name: &str) -> Result<String> {
+        self.run(self.db.org_id(name))
+    }
+
+    /// Creates a token. Its secret is only available from the result.
+    pub fn create_authorization(&self, authorization: &NewAuthorization) -> Result<Authorization> {
+        self.run(self.db.create_authorization(authorization))
+    }
+
+    /// See `AsyncDb::authorizations`
+    pub fn authorizations(&self) -> Result<Vec<Authorization>> {
+        self.run(self.db.authorizations())
+    }
+
+    /// Activates or deactivates a token
+    pub fn set_authorization_status(&self, id: &str, status: Status) -> Result<Authorization> {
+        self.run(self.db.set_authorization_status(id, status))
+    }
+
+    pub fn delete_authorization(&self, id: &str) -> Result<()> {
+        self.run(self.db.delete_authorization(id))
+    }
+
+    pub fn create_dbrp_mapping(&self, mapping: &NewDbrpMapping) -> Result<DbrpMapping> {
+        self.run(self.db.create_dbrp_mapping(mapping))
+    }
+
+    /// See `AsyncDb::dbrp_mappings`
+    pub fn dbrp_mappings(&self, database: Option<&str>) -> Result<Vec<DbrpMapping>> {
+        self.run(self.db.dbrp_mappings(database))
+    }
+
+    pub fn delete_dbrp_mapping(&self, id: &str) -> Result<()> {
+        self.run(self.db.delete_dbrp_mapping(id))
+    }
+
+    fn run<F>(&self, future: F) -> Result<F::Item>
+        where F: Future<Error = Error>
+    {
+        self.core.borrow_mut().run(future)
+    }
+}
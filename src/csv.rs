@@ -0,0 +1,107 @@
+//! Just enough RFC 4180 CSV to read query results as they stream in
+
+use std::mem;
+
+/// Splits bytes into rows as they arrive, keeping line breaks inside
+/// quoted values
+pub struct Rows {
+    /// Bytes of a row that hasn't ended yet
+    partial: Vec<u8>,
+    in_quotes: bool,
+}
+
+impl Rows {
+    pub fn new() -> Self {
+        Rows {
+            partial: Vec::new(),
+            in_quotes: false,
+        }
+    }
+
+    /// Adds `bytes`, calling `row` with each row they complete
+    pub fn push<F>(&mut self, bytes: &[u8], mut row: F)
+        where F: FnMut(Vec<u8>)
+    {
+        for &b in bytes {
+            match b {
+                b'"' => self.in_quotes = !self.in_quotes,
+                b'\n' if !self.in_quotes => {
+                    row(mem::replace(&mut self.partial, Vec::new()));
+                    continue;
+                }
+                _ => {}
+            }
+            self.partial.push(b);
+        }
+    }
+
+    /// The last row, which needn't end with a line break, or `None` if
+    /// the data ended inside a quoted value
+    pub fn finish(&mut self) -> Option<Vec<u8>> {
+        let row = mem::replace(&mut self.partial, Vec::new());
+        if self.in_quotes {
+            None
+        } else {
+            Some(row)
+        }
+    }
+}
+
+/// Splits a row into its cells, removing quotes and unescaping doubled
+/// quotes
+pub fn split_cells(row: &str) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut in_quotes = false;
+    let mut chars = row.trim_end_matches('\r').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => cells.push(mem::replace(&mut cell, String::new())),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+}
+
+#[cfg(test)]
+mod test {
+    use super::{split_cells, Rows};
+
+    fn rows(chunks: &[&str]) -> Option<Vec<String>> {
+        let mut rows = Rows::new();
+        let mut complete = Vec::new();
+        for chunk in chunks {
+            rows.push(chunk.as_bytes(), |row| complete.push(String::from_utf8(row).unwrap()));
+        }
+        rows.finish().map(|last| {
+            complete.push(String::from_utf8(last).unwrap());
+            complete
+        })
+    }
+
+    #[test]
+    fn rows_are_split_on_line_breaks_outside_quotes() {
+        assert_eq!(rows(&["a,b\n1,\"two\nlines\"\n", "3,4"]).unwrap(),
+                   ["a,b", "1,\"two\nlines\"", "3,4"]);
+        assert_eq!(rows(&["a,", "b\n1,2\n"]).unwrap(), ["a,b", "1,2", ""]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_detected() {
+        assert_eq!(rows(&["a,\"b\n"]), None);
+    }
+
+    #[test]
+    fn cells_are_unquoted() {
+        assert_eq!(split_cells(r#",plain,"with, comma","with ""quotes"""#),
+                   ["", "plain", "with, comma", r#"with "quotes""#]);
+        assert_eq!(split_cells("a,b\r"), ["a", "b"]);
+    }
+}
@@ -0,0 +1,635 @@
+//! Flux queries against InfluxDB 2.x (and 1.8's `/api/v2/query`).
+//!
+//! Results arrive as [annotated CSV][], which is parsed as it streams
+//! in so that large results don't have to be held in memory.
+//!
+//! [annotated CSV]: https://docs.influxdata.com/influxdb/v2.0/reference/syntax/annotated-csv/
+//!
+//! ```no_run
+//! extern crate futures;
+//! extern crate influxdb;
+//! extern crate tokio_core;
+//!
+//! use futures::Stream;
+//! use influxdb::AsyncDb;
+//! use influxdb::flux::FluxQuery;
+//!
+//! fn main() {
+//!     let mut core = tokio_core::reactor::Core::new()
+//!         .expect("Unable to create reactor core");
+//!     let async_db = AsyncDb::v2(core.handle(), "http://localhost:8086/", "my_org", "my_bucket", "my_token")
+//!         .expect("Unable to create AsyncDb");
+//!
+//!     let query = FluxQuery::new(r#"from(bucket: params.bucket) |> range(start: -1h)"#)
+//!         .param("bucket", "my_bucket");
+//!     let rows = async_db.flux_query(&query).for_each(|record| {
+//!         println!("{:?} {:?}", record.get("_time"), record.get("_value"));
+//!         Ok(())
+//!     });
+//!     core.run(rows).expect("Unable to run future to completion");
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use futures::{self, Async, Future, Stream};
+use hyper::{self, Chunk};
+use hyper::client::Connect;
+use serde_json;
+
+use {check_response_code, csv, rfc3339, AsyncDb, Error, Precision, Result};
+
+/// A Flux script and the values it can refer to as `params`
+#[derive(Debug, Clone)]
+pub struct FluxQuery {
+    query: String,
+    params: serde_json::Map<String, serde_json::Value>,
+    now: Option<SystemTime>,
+}
+
+impl FluxQuery {
+    pub fn new<S>(query: S) -> Self
+        where S: Into<String>
+    {
+        FluxQuery {
+            query: query.into(),
+            params: serde_json::Map::new(),
+            now: None,
+        }
+    }
+
+    /// Makes `value` available to the script as `params.<name>`
+    pub fn param<K, V>(mut self, name: K, value: V) -> Self
+        where K: Into<String>,
+              V: Into<serde_json::Value>,
+    {
+        self.params.insert(name.into(), value.into());
+        self
+    }
+
+    /// Runs the script as if it were `now`, which affects relative
+    /// times such as `range(start: -1h)`
+    pub fn now(mut self, now: SystemTime) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    fn to_json(&self) -> Result<Vec<u8>> {
+        let body = QueryBody {
+            query: &self.query,
+            query_type: "flux",
+            dialect: Dialect {
+                annotations: &["datatype", "group", "default"],
+                header: true,
+                delimiter: ",",
+            },
+            params: if self.params.is_empty() { None } else { Some(&self.params) },
+            now: self.now.map(rfc3339::format),
+        };
+        serde_json::to_vec(&body).map_err(Error::Serde)
+    }
+}
+
+#[derive(Serialize)]
+struct QueryBody<'a> {
+    query: &'a str,
+    #[serde(rename = "type")]
+    query_type: &'static str,
+    dialect: Dialect,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<&'a serde_json::Map<String, serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    now: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Dialect {
+    annotations: &'static [&'static str],
+    header: bool,
+    delimiter: &'static str,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    /// Runs a Flux query, yielding each row of each table as it is
+    /// parsed. An error reported partway through the results ends the
+    /// stream.
+    pub fn flux_query(&self, query: &FluxQuery) -> FluxRecords {
+        let mut query_endpoint = match self.base_url.join("/api/v2/query") {
+            Ok(url) => url,
+            Err(e) => return FluxRecords(Box::new(futures::stream::once(Err(e.into())))),
+        };
+        if let Some(ref org) = self.org {
+            query_endpoint.query_pairs_mut().append_pair("org", org);
+        }
+
+        let body = match query.to_json() {
+            Ok(body) => body,
+            Err(e) => return FluxRecords(Box::new(futures::stream::once(Err(e)))),
+        };
+
+        let mut request = self.request(hyper::Method::Post, &query_endpoint);
+        request.headers_mut().set(hyper::header::ContentType::json());
+        request.headers_mut().set_raw("Accept", "application/csv");
+        request.set_body(body);
+
+        let chunks =
+            self.client.request(request)
+            .map_err(Error::Hyper)
+            .and_then(check_response_code)
+            .map(|response| response.body().map_err(Error::Hyper))
+            .flatten_stream();
+
+        FluxRecords(Box::new(ParseRecords {
+            chunks: chunks,
+            parser: AnnotatedCsv::new(),
+            parsed: VecDeque::new(),
+            done: false,
+        }))
+    }
+}
+
+#[must_use = "streams do nothing unless polled"]
+pub struct FluxRecords(Box<Stream<Item = FluxRecord, Error = Error>>);
+
+impl Stream for FluxRecords {
+    type Item = FluxRecord;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        self.0.poll()
+    }
+}
+
+/// The type of a column, from the `#datatype` annotation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DataType {
+    String,
+    Double,
+    Boolean,
+    Long,
+    UnsignedLong,
+    Duration,
+    Base64Binary,
+    /// `dateTime:RFC3339` or `dateTime:RFC3339Nano`
+    DateTime,
+    /// `dateTime:number`; nanoseconds since the UNIX epoch
+    DateTimeNumber,
+}
+
+impl DataType {
+    fn from_annotation(annotation: &str) -> Result<Self> {
+        Ok(match annotation {
+            "string" | "" => DataType::String,
+            "double" => DataType::Double,
+            "boolean" => DataType::Boolean,
+            "long" => DataType::Long,
+            "unsignedLong" => DataType::UnsignedLong,
+            "duration" => DataType::Duration,
+            "base64Binary" => DataType::Base64Binary,
+            "dateTime:RFC3339" | "dateTime:RFC3339Nano" => DataType::DateTime,
+            "dateTime:number" => DataType::DateTimeNumber,
+            _ => return Err(Error::FluxParse(format!("unknown datatype {:?}", annotation))),
+        })
+    }
+
+    fn parse(&self, value: &str) -> Result<FluxValue> {
+        fn invalid(data_type: &DataType, value: &str) -> Error {
+            Error::FluxParse(format!("{:?} is not a valid {:?}", value, data_type))
+        }
+
+        Ok(match *self {
+            DataType::String => FluxValue::String(value.to_owned()),
+            DataType::Double => {
+                let value = match value {
+                    "+Inf" => ::std::f64::INFINITY,
+                    "-Inf" => ::std::f64::NEG_INFINITY,
+                    _ => value.parse().map_err(|_| invalid(self, value))?,
+                };
+                FluxValue::Double(value)
+            }
+            DataType::Boolean => {
+                match value {
+                    "true" => FluxValue::Boolean(true),
+                    "false" => FluxValue::Boolean(false),
+                    _ => return Err(invalid(self, value)),
+                }
+            }
+            DataType::Long => FluxValue::Long(value.parse().map_err(|_| invalid(self, value))?),
+            DataType::UnsignedLong => FluxValue::UnsignedLong(value.parse().map_err(|_| invalid(self, value))?),
+            DataType::Duration => FluxValue::Duration(value.to_owned()),
+            DataType::Base64Binary => FluxValue::Base64Binary(value.to_owned()),
+            DataType::DateTime => FluxValue::DateTime(rfc3339::parse(value).ok_or_else(|| invalid(self, value))?),
+            DataType::DateTimeNumber => {
+                let time = value.parse().ok()
+                    .and_then(|nanoseconds| Precision::Nanoseconds.system_time(nanoseconds))
+                    .ok_or_else(|| invalid(self, value))?;
+                FluxValue::DateTime(time)
+            }
+        })
+    }
+}
+
+/// A single cell of a table
+#[derive(Debug, Clone, PartialEq)]
+pub enum FluxValue {
+    /// An empty cell without a default
+    Null,
+    String(String),
+    Double(f64),
+    Boolean(bool),
+    Long(i64),
+    UnsignedLong(u64),
+    /// A Flux duration literal, such as `1h30m`
+    Duration(String),
+    /// Base64 encoded bytes
+    Base64Binary(String),
+    DateTime(SystemTime),
+}
+
+impl FluxValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            FluxValue::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            FluxValue::Double(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            FluxValue::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            FluxValue::Boolean(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_system_time(&self) -> Option<SystemTime> {
+        match *self {
+            FluxValue::DateTime(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxColumn {
+    pub name: String,
+    pub data_type: DataType,
+    /// Whether the column is part of the group key, from the `#group`
+    /// annotation
+    pub group: bool,
+    /// Used for empty cells, from the `#default` annotation
+    pub default: FluxValue,
+}
+
+/// One row of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct FluxRecord {
+    columns: Rc<Vec<FluxColumn>>,
+    values: Vec<FluxValue>,
+}
+
+impl FluxRecord {
+    /// The columns of the table this row is from
+    pub fn columns(&self) -> &[FluxColumn] {
+        &self.columns
+    }
+
+    /// The values, in the same order as `columns`
+    pub fn values(&self) -> &[FluxValue] {
+        &self.values
+    }
+
+    pub fn get(&self, column: &str) -> Option<&FluxValue> {
+        self.columns.iter().position(|c| c.name == column).map(|i| &self.values[i])
+    }
+
+    /// The name given to the result by `yield`
+    pub fn result(&self) -> Option<&str> {
+        self.get("result").and_then(FluxValue::as_str)
+    }
+
+    /// Which table of the result this row is from
+    pub fn table(&self) -> Option<i64> {
+        self.get("table").and_then(FluxValue::as_i64)
+    }
+}
+
+/// Parses records from the chunks of the response body as they arrive
+struct ParseRecords<S> {
+    chunks: S,
+    parser: AnnotatedCsv,
+    parsed: VecDeque<Result<FluxRecord>>,
+    done: bool,
+}
+
+impl<S> Stream for ParseRecords<S>
+    where S: Stream<Item = Chunk, Error = Error>
+{
+    type Item = FluxRecord;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(record) = self.parsed.pop_front() {
+                if record.is_err() {
+                    self.parsed.clear();
+                    self.done = true;
+                }
+                return record.map(|r| Async::Ready(Some(r)));
+            }
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            match self.chunks.poll()? {
+                Async::Ready(Some(chunk)) => self.parser.push(&chunk, &mut self.parsed),
+                Async::Ready(None) => {
+                    self.parser.finish(&mut self.parsed);
+                    self.done = true;
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+/// A push parser for annotated CSV, which is RFC 4180 CSV where
+/// annotation rows start with `#` and each table begins with its own
+/// annotations and header.
+struct AnnotatedCsv {
+    rows: csv::Rows,
+    annotations: Annotations,
+    columns: Option<Rc<Vec<FluxColumn>>>,
+}
+
+#[derive(Default)]
+struct Annotations {
+    datatype: Vec<String>,
+    group: Vec<String>,
+    default: Vec<String>,
+}
+
+impl AnnotatedCsv {
+    fn new() -> Self {
+        AnnotatedCsv {
+            rows: csv::Rows::new(),
+            annotations: Annotations::default(),
+            columns: None,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8], parsed: &mut VecDeque<Result<FluxRecord>>) {
+        let mut rows = Vec::new();
+        self.rows.push(bytes, |row| rows.push(row));
+        for row in rows {
+            if let Some(record) = self.row(row) {
+                parsed.push_back(record);
+            }
+        }
+    }
+
+    fn finish(&mut self, parsed: &mut VecDeque<Result<FluxRecord>>) {
+        match self.rows.finish() {
+            Some(row) => {
+                if let Some(record) = self.row(row) {
+                    parsed.push_back(record);
+                }
+            }
+            None => parsed.push_back(Err(Error::FluxParse(String::from("the response ended inside a quoted value")))),
+        }
+    }
+
+    fn row(&mut self, row: Vec<u8>) -> Option<Result<FluxRecord>> {
+        let row = match String::from_utf8(row) {
+            Ok(row) => row,
+            Err(e) => return Some(Err(Error::Utf8(e.utf8_error()))),
+        };
+        let row = row.trim_end_matches('\r');
+
+        // A blank line ends a table; the next starts with new annotations
+        if row.is_empty() {
+            self.annotations = Annotations::default();
+            self.columns = None;
+            return None;
+        }
+
+        let mut cells = csv::split_cells(row);
+        if cells[0].starts_with('#') {
+            if self.columns.is_some() {
+                self.annotations = Annotations::default();
+                self.columns = None;
+            }
+            let name = cells.remove(0);
+            match name.as_str() {
+                "#datatype" => self.annotations.datatype = cells,
+                "#group" => self.annotations.group = cells,
+                "#default" => self.annotations.default = cells,
+                _ => {}
+            }
+            return None;
+        }
+
+        // The first cell is under the annotation names, so isn't data
+        cells.remove(0);
+
+        let columns = match self.columns {
+            Some(ref columns) => columns.clone(),
+            None => {
+                match self.header(cells) {
+                    Ok(columns) => self.columns = Some(Rc::new(columns)),
+                    Err(e) => return Some(Err(e)),
+                }
+                return None;
+            }
+        };
+
+        if cells.len() != columns.len() {
+            return Some(Err(Error::FluxParse(format!("expected {} values but found {}", columns.len(), cells.len()))));
+        }
+
+        // Errors partway through the results are reported as a table
+        if !columns.is_empty() && columns[0].name == "error" &&
+           columns.iter().all(|c| c.name == "error" || c.name == "reference") {
+            return Some(Err(Error::Flux(cells.swap_remove(0))));
+        }
+
+        let values: Result<Vec<_>> = cells.iter().zip(columns.iter())
+            .map(|(cell, column)| {
+                if cell.is_empty() {
+                    Ok(column.default.clone())
+                } else {
+                    column.data_type.parse(cell)
+                }
+            })
+            .collect();
+
+        Some(values.map(|values| FluxRecord { columns: columns, values: values }))
+    }
+
+    fn header(&self, names: Vec<String>) -> Result<Vec<FluxColumn>> {
+        names.into_iter().enumerate()
+            .map(|(i, name)| {
+                let data_type = match self.annotations.datatype.get(i) {
+                    Some(annotation) => DataType::from_annotation(annotation)?,
+                    None => DataType::String,
+                };
+                let default = match self.annotations.default.get(i) {
+                    Some(default) if !default.is_empty() => data_type.parse(default)?,
+                    _ => FluxValue::Null,
+                };
+
+                Ok(FluxColumn {
+                    name: name,
+                    data_type: data_type,
+                    group: self.annotations.group.get(i).map_or(false, |g| g == "true"),
+                    default: default,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    const TWO_TABLES: &'static str = "\
+#datatype,string,long,dateTime:RFC3339,double,string,string
+#group,false,false,false,false,true,true
+#default,_result,,,,,
+,result,table,_time,_value,_field,host
+,,0,2015-06-11T20:46:02Z,0.64,value,server01
+,,0,2015-06-11T20:46:03Z,0.72,value,server01
+
+#datatype,string,long,dateTime:RFC3339,long,string,string
+#group,false,false,false,false,true,true
+#default,_result,,,,,
+,result,table,_time,_value,_field,host
+,,1,2015-06-11T20:46:02Z,3,count,\"server, \"\"two\"\"\"
+";
+
+    fn parse(chunks: &[&str]) -> Vec<Result<FluxRecord>> {
+        let mut parser = AnnotatedCsv::new();
+        let mut parsed = VecDeque::new();
+        for chunk in chunks {
+            parser.push(chunk.as_bytes(), &mut parsed);
+        }
+        parser.finish(&mut parsed);
+        parsed.into_iter().collect()
+    }
+
+    #[test]
+    fn tables_are_parsed_with_typed_columns() {
+        let records: Vec<_> = parse(&[TWO_TABLES]).into_iter().map(|r| r.unwrap()).collect();
+
+        assert_eq!(records.len(), 3);
+
+        assert_eq!(records[0].result(), Some("_result"));
+        assert_eq!(records[0].table(), Some(0));
+        assert_eq!(records[0].get("_time").and_then(FluxValue::as_system_time),
+                   Some(UNIX_EPOCH + Duration::from_secs(1434055562)));
+        assert_eq!(records[0].get("_value").and_then(FluxValue::as_f64), Some(0.64));
+        assert_eq!(records[1].get("_value").and_then(FluxValue::as_f64), Some(0.72));
+
+        let host = &records[0].columns()[5];
+        assert_eq!(host.name, "host");
+        assert_eq!(host.data_type, DataType::String);
+        assert!(host.group);
+
+        assert_eq!(records[2].table(), Some(1));
+        assert_eq!(records[2].get("_value").and_then(FluxValue::as_i64), Some(3));
+        assert_eq!(records[2].get("host").and_then(FluxValue::as_str), Some(r#"server, "two""#));
+    }
+
+    #[test]
+    fn rows_can_span_chunks() {
+        let split: Vec<_> = TWO_TABLES.as_bytes().chunks(7)
+            .map(|chunk| ::std::str::from_utf8(chunk).unwrap())
+            .collect();
+
+        let whole = parse(&[TWO_TABLES]);
+        let pieces = parse(&split);
+        assert_eq!(pieces.len(), whole.len());
+        for (a, b) in pieces.iter().zip(whole.iter()) {
+            assert_eq!(a.as_ref().unwrap(), b.as_ref().unwrap());
+        }
+    }
+
+    #[test]
+    fn empty_cells_use_the_default_or_null() {
+        let csv = "\
+#datatype,string,long,boolean,double
+#group,false,false,false,false
+#default,_result,,true,
+,result,table,ok,value
+,,0,,
+";
+        let records = parse(&[csv]);
+        let record = records[0].as_ref().unwrap();
+
+        assert_eq!(record.get("ok"), Some(&FluxValue::Boolean(true)));
+        assert_eq!(record.get("value"), Some(&FluxValue::Null));
+    }
+
+    #[test]
+    fn errors_in_the_results_are_reported() {
+        let csv = "\
+#datatype,string,string
+#group,true,true
+#default,,
+,error,reference
+,\"failed to execute query: bucket not found\",
+";
+        match parse(&[csv]).pop() {
+            Some(Err(Error::Flux(ref message))) if message == "failed to execute query: bucket not found" => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_values_are_reported() {
+        let csv = "\
+#datatype,string,long
+,result,table
+,,zero
+";
+        match parse(&[csv]).pop() {
+            Some(Err(Error::FluxParse(_))) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn the_query_is_sent_as_json() {
+        let query = FluxQuery::new("from(bucket: params.b)")
+            .param("b", "my_bucket")
+            .now(UNIX_EPOCH);
+        let json: serde_json::Value = serde_json::from_slice(&query.to_json().unwrap()).unwrap();
+
+        assert_eq!(json["query"], "from(bucket: params.b)");
+        assert_eq!(json["type"], "flux");
+        assert_eq!(json["params"]["b"], "my_bucket");
+        assert_eq!(json["now"], "1970-01-01T00:00:00Z");
+        assert_eq!(json["dialect"]["annotations"][0], "datatype");
+    }
+}
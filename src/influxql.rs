@@ -0,0 +1,199 @@
+//! Building InfluxQL statements without the risk of values being
+//! interpreted as part of the statement.
+
+use measurement::TimestampValue;
+use {Error, Result};
+
+/// Quotes an identifier, such as a database, measurement, or tag name
+pub fn quote_identifier(identifier: &str) -> String {
+    quote(identifier, '"')
+}
+
+/// Quotes a string literal, such as a tag value
+pub fn quote_string(value: &str) -> String {
+    quote(value, '\'')
+}
+
+fn quote(value: &str, quote: char) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push(quote);
+    for c in value.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '\\' => quoted.push_str("\\\\"),
+            c if c == quote => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push(quote);
+    quoted
+}
+
+/// Selects the series, and for `DELETE` the points, that a `DELETE` or
+/// `DROP SERIES` statement applies to.
+///
+/// ```
+/// # use influxdb::influxql::SeriesFilter;
+/// let filter = SeriesFilter::new()
+///     .measurement("cpu")
+///     .tag("customer", "acme")
+///     .end(1434055562000000000i64).unwrap();
+///
+/// assert_eq!(filter.delete_statement().unwrap(),
+///            r#"DELETE FROM "cpu" WHERE "customer" = 'acme' AND time < 1434055562000000000"#);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeriesFilter {
+    measurement: Option<String>,
+    tags: Vec<(String, String)>,
+    start: Option<i64>,
+    end: Option<i64>,
+}
+
+impl SeriesFilter {
+    pub fn new() -> Self {
+        SeriesFilter::default()
+    }
+
+    pub fn measurement<S>(mut self, measurement: S) -> Self
+        where S: Into<String>
+    {
+        self.measurement = Some(measurement.into());
+        self
+    }
+
+    /// Only series where the tag `key` has exactly `value`. Multiple
+    /// tags must all match.
+    pub fn tag<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<String>,
+              V: Into<String>,
+    {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    /// Only points at or after `time`. Not allowed by `DROP SERIES`.
+    pub fn start<T>(mut self, time: T) -> Result<Self>
+        where T: TimestampValue
+    {
+        self.start = Some(time.nanoseconds()?);
+        Ok(self)
+    }
+
+    /// Only points before `time`. Not allowed by `DROP SERIES`.
+    pub fn end<T>(mut self, time: T) -> Result<Self>
+        where T: TimestampValue
+    {
+        self.end = Some(time.nanoseconds()?);
+        Ok(self)
+    }
+
+    pub fn delete_statement(&self) -> Result<String> {
+        self.statement("DELETE")
+    }
+
+    pub fn drop_series_statement(&self) -> Result<String> {
+        if self.start.is_some() || self.end.is_some() {
+            return Err(Error::InvalidStatement(String::from("DROP SERIES cannot filter by time")));
+        }
+        self.statement("DROP SERIES")
+    }
+
+    fn statement(&self, command: &str) -> Result<String> {
+        let mut conditions: Vec<_> = self.tags.iter()
+            .map(|&(ref key, ref value)| format!("{} = {}", quote_identifier(key), quote_string(value)))
+            .collect();
+        if let Some(start) = self.start {
+            conditions.push(format!("time >= {}", start));
+        }
+        if let Some(end) = self.end {
+            conditions.push(format!("time < {}", end));
+        }
+
+        if self.measurement.is_none() && conditions.is_empty() {
+            // InfluxQL has no way to say "everything" here, and it's
+            // unlikely to be what was wanted
+            return Err(Error::InvalidStatement(format!("{} needs a measurement or a condition", command)));
+        }
+
+        let mut statement = String::from(command);
+        if let Some(ref measurement) = self.measurement {
+            statement.push_str(" FROM ");
+            statement.push_str(&quote_identifier(measurement));
+        }
+        if !conditions.is_empty() {
+            statement.push_str(" WHERE ");
+            statement.push_str(&conditions.join(" AND "));
+        }
+        Ok(statement)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Error;
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!(quote_identifier("cpu"), r#""cpu""#);
+        assert_eq!(quote_identifier(r#"my "db""#), r#""my \"db\"""#);
+        assert_eq!(quote_identifier(r"back\slash"), r#""back\\slash""#);
+    }
+
+    #[test]
+    fn strings_are_quoted() {
+        assert_eq!(quote_string("acme"), "'acme'");
+        assert_eq!(quote_string("acme' OR 'a' = 'a"), r"'acme\' OR \'a\' = \'a'");
+        assert_eq!(quote_string("line\nbreak"), r"'line\nbreak'");
+        assert_eq!(quote_string(r"trailing\"), r"'trailing\\'");
+    }
+
+    #[test]
+    fn delete_by_tag() {
+        let filter = SeriesFilter::new().tag("customer", "acme").tag("region", "us-west");
+
+        assert_eq!(filter.delete_statement().unwrap(),
+                   r#"DELETE WHERE "customer" = 'acme' AND "region" = 'us-west'"#);
+    }
+
+    #[test]
+    fn delete_by_time() {
+        let filter = SeriesFilter::new()
+            .measurement("cpu")
+            .start(10i64).unwrap()
+            .end(20i64).unwrap();
+
+        assert_eq!(filter.delete_statement().unwrap(),
+                   r#"DELETE FROM "cpu" WHERE time >= 10 AND time < 20"#);
+    }
+
+    #[test]
+    fn drop_series() {
+        let filter = SeriesFilter::new().measurement("cpu").tag("customer", "acme");
+
+        assert_eq!(filter.drop_series_statement().unwrap(),
+                   r#"DROP SERIES FROM "cpu" WHERE "customer" = 'acme'"#);
+    }
+
+    #[test]
+    fn drop_series_cannot_filter_by_time() {
+        let filter = SeriesFilter::new().measurement("cpu").start(10i64).unwrap();
+
+        match filter.drop_series_statement() {
+            Err(Error::InvalidStatement(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_empty_filter_is_rejected() {
+        match SeriesFilter::new().delete_statement() {
+            Err(Error::InvalidStatement(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
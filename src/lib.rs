@@ -34,15 +34,21 @@
 //! #[influx(rename = "my_measure")]
 //! struct MyMeasure {
 //!     // Specify which struct fields are InfluxDB tags.
-//!     // Tags must be `String`s or `&str`s.
+//!     // Tags can be anything that implements `Display`, such as
+//!     // strings, integers, booleans, and IP addresses.
 //!     #[influx(tag)]
 //!     region: String,
 //!     // Specify which struct fields are InfluxDB fields.
 //!     // Supported types are integers, floats, strings, and booleans.
 //!     // The rename annotation works with struct fields as well.
+//!     // Tags and fields may also be `Option`s; `None` values are
+//!     // left out of the data sent to InfluxDB.
 //!     #[influx(field, rename = "amount")]
 //!     count: i32,
 //!     // Specify which struct field is the InfluxDB timestamp.
+//!     // `SystemTime`, `Duration` since the UNIX epoch, and `i64`
+//!     // nanoseconds are supported, as are `chrono::DateTime` and
+//!     // `time::Timespec` with the `chrono` and `time` features.
 //!     #[influx(timestamp)]
 //!     when: SystemTime,
 //!     // Struct fields that aren't annotated won't be sent to InfluxDB.
@@ -71,27 +77,60 @@
 //! ```
 
 extern crate tokio_core;
+#[cfg(feature = "mock")]
+extern crate tokio_io;
 extern crate hyper;
 extern crate url;
 extern crate futures;
 extern crate serde;
+#[cfg_attr(feature = "mock", macro_use)]
 extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate quick_error;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "time")]
+extern crate time;
+#[cfg(unix)]
+extern crate tokio_uds;
 
-use std::net::SocketAddr;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::vec;
 
 use futures::future::Either;
-use futures::{Future, Stream, BoxFuture};
-use hyper::client::{self, HttpConnector};
+use futures::{Async, Future, Stream};
+use hyper::client::{self, Connect, HttpConnector};
 use tokio_core::net::UdpSocket;
 use tokio_core::reactor::Handle;
 
+mod csv;
+pub mod flux;
+pub mod influxql;
 pub mod measurement;
+mod rfc3339;
+pub mod sql;
+pub mod v2;
+pub mod version;
+pub use influxql::SeriesFilter;
 pub use measurement::Measurement;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(unix)]
+pub mod unix;
+#[cfg(unix)]
+pub use unix::AsyncUnixDatagramDb;
+
 // TODO: documentation
 
 quick_error! {
@@ -119,6 +158,33 @@ quick_error! {
             description("The InfluxDB server responded with an error")
             display("The InfluxDB server responded with an error: {}", what)
         }
+        Statement(what: String) {
+            description("The InfluxDB server was unable to run the statement")
+            display("The InfluxDB server was unable to run the statement: {}", what)
+        }
+        NotExecuted {
+            description("The InfluxDB server did not run the statement")
+        }
+        InvalidStatement(what: String) {
+            description("Unable to build the InfluxQL statement")
+            display("Unable to build the InfluxQL statement: {}", what)
+        }
+        TimeColumn(what: String) {
+            description("Unable to decode the time column")
+            display("Unable to decode the time column: {}", what)
+        }
+        Flux(message: String) {
+            description("The InfluxDB server was unable to run the Flux query")
+            display("The InfluxDB server was unable to run the Flux query: {}", message)
+        }
+        FluxParse(what: String) {
+            description("Unable to parse the Flux query results")
+            display("Unable to parse the Flux query results: {}", what)
+        }
+        SqlParse(what: String) {
+            description("Unable to parse the SQL query results")
+            display("Unable to parse the SQL query results: {}", what)
+        }
         AddrParse(error: std::net::AddrParseError) {
             description(error.description())
             display("Unable to parse the address: {}", error)
@@ -131,44 +197,139 @@ quick_error! {
             from()
             cause(error)
         }
+        Unix(error: std::io::Error) {
+            description(error.description())
+            display("Unable to send to the Unix socket: {}", error)
+            cause(error)
+        }
+        Reactor(error: std::io::Error) {
+            description(error.description())
+            display("Unable to create the reactor core: {}", error)
+            cause(error)
+        }
+        Io(error: std::io::Error) {
+            description(error.description())
+            display("Unable to write the line protocol: {}", error)
+            cause(error)
+        }
+        Utf8(error: std::str::Utf8Error) {
+            description(error.description())
+            display("The line protocol was not valid UTF-8: {}", error)
+            from()
+            cause(error)
+        }
+        NonFiniteFloat(value: f64) {
+            description("InfluxDB cannot store NaN or infinite floats")
+            display("InfluxDB cannot store the float {}", value)
+        }
+        Unescapable(value: String) {
+            description("The line protocol cannot escape line breaks")
+            display("The line protocol cannot escape the line breaks in {:?}", value)
+        }
+        NoFields(measurement: String) {
+            description("InfluxDB requires that a measurement has at least one field")
+            display("InfluxDB requires that a measurement has at least one field, but {} has none", measurement)
+        }
+        Format(error: std::fmt::Error) {
+            description(error.description())
+            display("Unable to format the line protocol: {}", error)
+            from()
+            cause(error)
+        }
+        PointTooLarge(size: usize, max_payload_size: usize) {
+            description("A point is larger than the maximum datagram payload size")
+            display("A point of {} bytes is larger than the maximum datagram payload size of {} bytes",
+                    size, max_payload_size)
+        }
+        TimestampOutOfRange {
+            description("The timestamp is outside the range InfluxDB supports")
+            display("The timestamp is outside the range InfluxDB supports \
+                     (1677-09-21T00:12:43.145224194Z to 2262-04-11T23:47:16.854775806Z)")
+        }
+        OrgNotFound(name: String) {
+            description("The InfluxDB server has no organization with that name")
+            display("The InfluxDB server has no organization called {:?}", name)
+        }
+        UnknownVersion {
+            description("The InfluxDB server did not say which version it is")
+        }
+        UnsupportedByV2(what: &'static str) {
+            description("InfluxDB 2.x does not support the operation")
+            display("InfluxDB 2.x does not support {}; use the bucket APIs instead", what)
+        }
     }
 }
 
-type Result<T> = ::std::result::Result<T, Error>;
+pub type Result<T> = ::std::result::Result<T, Error>;
 
-pub struct AsyncDb {
+/// Sends data to and queries InfluxDB's HTTP API.
+///
+/// Connections are made over TCP by default; `C` can be any hyper
+/// connector, such as `unix::UnixConnector`.
+pub struct AsyncDb<C = HttpConnector> {
     name: String,
+    base_url: url::Url,
     query_endpoint: url::Url,
     write_endpoint: url::Url,
-    client: hyper::Client<HttpConnector>,
+    client: hyper::Client<C>,
+    /// hyper takes ownership of each request body, so the best we can
+    /// do is start the next one with enough room for the last
+    write_capacity: Cell<usize>,
+    epoch: Option<Precision>,
+    /// The organization, for InfluxDB 2.x
+    org: Option<String>,
+    token: Option<String>,
 }
 
 impl AsyncDb {
     pub fn new(handle: Handle, base_url: &str, name: &str) -> Result<Self> {
+        let client = hyper::Client::configure().keep_alive(false).build(&handle);
+        AsyncDb::with_client(client, base_url, name)
+    }
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    fn with_client(client: hyper::Client<C>, base_url: &str, name: &str) -> Result<Self> {
         let base_url = url::Url::parse(base_url)?;
         let query_endpoint = base_url.join("/query")?;
         let mut write_endpoint = base_url.join("/write")?;
         write_endpoint.query_pairs_mut()
             .append_pair("db", &name);
 
-        let client = hyper::Client::configure().keep_alive(false).build(&handle);
-
         Ok(AsyncDb {
             name: name.into(),
+            base_url: base_url,
             query_endpoint: query_endpoint,
             write_endpoint: write_endpoint,
             client: client,
+            write_capacity: Cell::new(0),
+            epoch: None,
+            org: None,
+            token: None,
         })
     }
 
+    /// Starts a request, authenticated if there is a token
+    fn request(&self, method: hyper::Method, url: &url::Url) -> client::Request {
+        let mut request = client::Request::new(method, url.as_str().parse().expect("Invalid request URL"));
+        if let Some(ref token) = self.token {
+            request.headers_mut().set(hyper::header::Authorization(format!("Token {}", token)));
+        }
+        request
+    }
+
     pub fn add_data<T>(&self, measure: T) -> AddData
         where T: Measurement
     {
-        let mut request = client::Request::new(
-            hyper::Method::Post, self.write_endpoint.as_str().parse().expect("Invalid request URL"));
-        let mut bytes_to_send = String::new();
-        measure.to_data(&mut bytes_to_send);
-        request.set_body(bytes_to_send.into_bytes());
+        let mut request = self.request(hyper::Method::Post, &self.write_endpoint);
+        let mut bytes_to_send = Vec::with_capacity(self.write_capacity.get());
+        if let Err(e) = measure.to_bytes(&mut bytes_to_send) {
+            return AddData(Box::new(futures::future::err(e)));
+        }
+        self.write_capacity.set(bytes_to_send.len());
+        request.set_body(bytes_to_send);
 
         let response =
             self.client.request(request)
@@ -179,37 +340,227 @@ impl AsyncDb {
         AddData(Box::new(response))
     }
 
+    /// Asks for the `time` column of query results as an integer count
+    /// of `epoch` units since the UNIX epoch, rather than as an RFC3339
+    /// string. Each `Series` returned records the epoch it was asked
+    /// for; see `Series::system_times`.
+    pub fn set_epoch(&mut self, epoch: Option<Precision>) {
+        self.epoch = epoch;
+    }
+
+    /// Authenticates every request with `token`, as InfluxDB 2.x and 3
+    /// require, or with nothing if it is `None`. `AsyncDb::v2` sets it
+    /// already.
+    pub fn set_token(&mut self, token: Option<&str>) {
+        self.token = token.map(String::from);
+    }
+
+    /// Runs a query, POSTing it if InfluxDB requires that or if it is
+    /// too long to fit in a URL and sending it with GET otherwise.
     pub fn query(&self, query: &str) -> Query {
+        if requires_post(query) || query.len() > MAX_GET_QUERY_LENGTH {
+            return self.query_post(query);
+        }
+
         let mut query_endpoint = self.query_endpoint.clone();
-        query_endpoint.query_pairs_mut()
-            .append_pair("db", &self.name)
+        {
+            let mut pairs = query_endpoint.query_pairs_mut();
+            pairs.append_pair("db", &self.name)
+                .append_pair("q", query);
+            if let Some(epoch) = self.epoch {
+                pairs.append_pair("epoch", epoch.as_str());
+            }
+        }
+
+        let epoch = self.epoch;
+        let response =
+            self.client.request(self.request(hyper::Method::Get, &query_endpoint))
+            .map_err(Error::Hyper)
+            .and_then(check_response_code)
+            .and_then(response_to_json)
+            .map(move |response: QueryResponse| response.with_epoch(epoch));
+
+        Query(Box::new(response))
+    }
+
+    /// Runs a query by POSTing it in a form-encoded body
+    pub fn query_post(&self, query: &str) -> Query {
+        let mut request = self.request(hyper::Method::Post, &self.query_endpoint);
+        let mut body = url::form_urlencoded::Serializer::new(String::new());
+        body.append_pair("db", &self.name)
             .append_pair("q", query);
+        if let Some(epoch) = self.epoch {
+            body.append_pair("epoch", epoch.as_str());
+        }
+        let body = body.finish();
+        request.headers_mut().set(hyper::header::ContentType::form_url_encoded());
+        request.set_body(body);
 
+        let epoch = self.epoch;
         let response =
-            self.client.get(query_endpoint.as_str().parse().expect("Invalid query URL"))
+            self.client.request(request)
             .map_err(Error::Hyper)
             .and_then(check_response_code)
-            .and_then(response_to_json);
+            .and_then(response_to_json)
+            .map(move |response: QueryResponse| response.with_epoch(epoch));
 
         Query(Box::new(response))
     }
+
+    /// Sends several statements in one request. The result of each
+    /// statement is at the same index as the statement, and a statement
+    /// that failed or wasn't run is an error.
+    pub fn query_batch<I>(&self, statements: I) -> QueryBatch
+        where I: IntoIterator,
+              I::Item: AsRef<str>,
+    {
+        let statements: Vec<_> = statements.into_iter()
+            .map(|statement| statement.as_ref().to_owned())
+            .collect();
+        if statements.is_empty() {
+            return QueryBatch(Box::new(futures::future::ok(Vec::new())));
+        }
+
+        let count = statements.len();
+        let response = self.query(&statements.join("; "))
+            .map(move |response| pair_results(response, count));
+
+        QueryBatch(Box::new(response))
+    }
+
+    /// Creates the database. Succeeds if it already exists.
+    ///
+    /// InfluxDB 2.x has buckets rather than databases, so this fails
+    /// for clients made with `AsyncDb::v2`; see `AsyncDb::create_bucket`.
+    pub fn create_database(&self) -> Execute {
+        if self.org.is_some() {
+            return Execute(Box::new(futures::future::err(Error::UnsupportedByV2("CREATE DATABASE"))));
+        }
+        self.execute(&format!("CREATE DATABASE {}", influxql::quote_identifier(&self.name)))
+    }
+
+    /// Drops the database and all of its data. Succeeds if it doesn't
+    /// exist.
+    ///
+    /// Like `create_database`, this fails for InfluxDB 2.x clients; see
+    /// `AsyncDb::delete_bucket`.
+    pub fn drop_database(&self) -> Execute {
+        if self.org.is_some() {
+            return Execute(Box::new(futures::future::err(Error::UnsupportedByV2("DROP DATABASE"))));
+        }
+        self.execute(&format!("DROP DATABASE {}", influxql::quote_identifier(&self.name)))
+    }
+
+    /// Deletes the points selected by `filter`, leaving the series
+    /// themselves in the index.
+    pub fn delete(&self, filter: &SeriesFilter) -> Execute {
+        match filter.delete_statement() {
+            Ok(statement) => self.execute(&statement),
+            Err(e) => Execute(Box::new(futures::future::err(e))),
+        }
+    }
+
+    /// Deletes the series selected by `filter` and all of their points
+    pub fn drop_series(&self, filter: &SeriesFilter) -> Execute {
+        match filter.drop_series_statement() {
+            Ok(statement) => self.execute(&statement),
+            Err(e) => Execute(Box::new(futures::future::err(e))),
+        }
+    }
+
+    /// Deletes a measurement, its series, and all of its points
+    pub fn drop_measurement(&self, measurement: &str) -> Execute {
+        self.execute(&format!("DROP MEASUREMENT {}", influxql::quote_identifier(measurement)))
+    }
+
+    /// Deletes a shard, as listed by `SHOW SHARDS`, and its points
+    pub fn drop_shard(&self, id: u64) -> Execute {
+        self.execute(&format!("DROP SHARD {}", id))
+    }
+
+    /// Runs a statement that changes the server and reports the first
+    /// error in its results.
+    fn execute(&self, statement: &str) -> Execute {
+        let response =
+            self.query_post(statement)
+            .and_then(|response| {
+                match response.results.into_iter().filter_map(|r| r.error).next() {
+                    Some(error) => Err(Error::Statement(error)),
+                    None => Ok(()),
+                }
+            });
+
+        Execute(Box::new(response))
+    }
+}
+
+/// Longer queries are POSTed, as servers and proxies commonly limit
+/// URLs to a few kilobytes.
+const MAX_GET_QUERY_LENGTH: usize = 2048;
+
+/// The statements InfluxDB refuses to run from a GET request
+const POST_ONLY_STATEMENTS: &'static [&'static str] = &[
+    "ALTER", "CREATE", "DELETE", "DROP", "GRANT", "KILL", "REVOKE",
+];
+
+/// Whether any statement in `query` modifies the server. Semicolons
+/// inside strings may cause false positives, which only cost a POST.
+fn requires_post(query: &str) -> bool {
+    query.split(';').any(|statement| {
+        let mut words = statement.split_whitespace();
+        match words.next() {
+            Some(first) if first.eq_ignore_ascii_case("SELECT") => {
+                words.any(|word| word.eq_ignore_ascii_case("INTO"))
+            }
+            Some(first) => {
+                POST_ONLY_STATEMENTS.iter().any(|keyword| first.eq_ignore_ascii_case(keyword))
+            }
+            None => false,
+        }
+    })
+}
+
+fn pair_results(response: QueryResponse, count: usize) -> Vec<Result<Vec<Series>>> {
+    let mut results: Vec<_> = (0..count).map(|_| Err(Error::NotExecuted)).collect();
+
+    for result in response.results {
+        if let Some(slot) = results.get_mut(result.statement_id) {
+            *slot = match result.error {
+                Some(error) => Err(Error::Statement(error)),
+                None => Ok(result.series),
+            };
+        }
+    }
+
+    results
 }
 
 fn check_response_code(resp: client::Response) -> Box<Future<Item = client::Response, Error = Error>> {
     let f = if resp.status().is_success() {
         Either::A(futures::future::ok(resp))
     } else {
-        let e = response_to_json::<InfluxServerError>(resp)
-            .and_then(|message| Err(Error::BadRequest(message.error)));
+        let e = response_body(resp)
+            .and_then(|body| Err(Error::BadRequest(server_error_message(&body))));
         Either::B(e)
     };
 
     Box::new(f)
 }
 
-fn response_to_json<T>(resp: client::Response) -> Box<Future<Item = T, Error = Error>>
-    where T: serde::Deserialize + 'static,
-{
+/// InfluxDB 1.x describes errors as `{"error": ...}` and 2.x as
+/// `{"code": ..., "message": ...}`. Anything else, such as a proxy's
+/// error page, is passed on as it is.
+fn server_error_message(body: &[u8]) -> String {
+    if let Ok(e) = serde_json::from_slice::<InfluxServerError>(body) {
+        return e.error;
+    }
+    if let Ok(e) = serde_json::from_slice::<v2::ServerError>(body) {
+        return e.message;
+    }
+    String::from_utf8_lossy(body).into_owned()
+}
+
+fn response_body(resp: client::Response) -> Box<Future<Item = Vec<u8>, Error = Error>> {
     let f =
         resp.body()
         .map_err(Error::Hyper)
@@ -218,7 +569,16 @@ fn response_to_json<T>(resp: client::Response) -> Box<Future<Item = T, Error = E
             // TODO: Is there some way of reusing the vector allocation?
             acc.extend_from_slice(&*chunk);
             futures::future::ok::<_, Error>(acc)
-        })
+        });
+
+    Box::new(f)
+}
+
+fn response_to_json<T>(resp: client::Response) -> Box<Future<Item = T, Error = Error>>
+    where T: serde::Deserialize + 'static,
+{
+    let f =
+        response_body(resp)
         .and_then(|body| serde_json::from_slice(&body).map_err(Error::Serde));
 
     Box::new(f)
@@ -248,11 +608,44 @@ impl Future for Query {
     }
 }
 
+#[must_use = "futures do nothing unless polled"]
+pub struct QueryBatch(Box<Future<Item = Vec<Result<Vec<Series>>>, Error = Error>>);
+
+impl Future for QueryBatch {
+    type Item = Vec<Result<Vec<Series>>>;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct Execute(Box<Future<Item = (), Error = Error>>);
+
+impl Future for Execute {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct QueryResponse {
     pub results: Vec<QueryResult>,
 }
 
+impl QueryResponse {
+    fn with_epoch(mut self, epoch: Option<Precision>) -> Self {
+        for series in self.results.iter_mut().flat_map(|result| result.series.iter_mut()) {
+            series.epoch = epoch;
+        }
+        self
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct QueryResult {
     #[serde(default)]
@@ -267,6 +660,100 @@ pub struct Series {
     pub columns: Vec<String>, // TODO: `time` is always added?
     pub values: Vec<Vec<serde_json::Value>>, // TODO: matches with columns?
     // TODO: Don't expose serde types publically
+    /// The units of `time`, when the query asked for epoch times with
+    /// `AsyncDb::set_epoch`
+    #[serde(skip_deserializing)]
+    pub epoch: Option<Precision>,
+}
+
+impl Series {
+    /// The `time` of each row, as returned when the `AsyncDb` has an
+    /// epoch set.
+    pub fn epoch_times(&self) -> Result<Vec<i64>> {
+        let index = self.columns.iter().position(|c| c == "time")
+            .ok_or_else(|| Error::TimeColumn(String::from("there is no time column")))?;
+
+        self.values.iter()
+            .map(|row| {
+                row.get(index)
+                    .and_then(|time| time.as_i64())
+                    .ok_or_else(|| Error::TimeColumn(format!("{:?} is not an integer epoch time", row.get(index))))
+            })
+            .collect()
+    }
+
+    /// The `time` of each row, decoded in the units of `epoch`
+    pub fn system_times(&self) -> Result<Vec<SystemTime>> {
+        let precision = self.epoch
+            .ok_or_else(|| Error::TimeColumn(String::from("the query did not ask for epoch times")))?;
+        self.epoch_times()?
+            .into_iter()
+            .map(|time| {
+                precision.system_time(time)
+                    .ok_or_else(|| Error::TimeColumn(format!("{} is out of range", time)))
+            })
+            .collect()
+    }
+}
+
+/// The units of an epoch time
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Precision {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+    Minutes,
+    Hours,
+}
+
+impl Precision {
+    /// The name InfluxDB uses for this precision
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Precision::Nanoseconds => "ns",
+            Precision::Microseconds => "u",
+            Precision::Milliseconds => "ms",
+            Precision::Seconds => "s",
+            Precision::Minutes => "m",
+            Precision::Hours => "h",
+        }
+    }
+
+    fn nanoseconds(&self) -> u64 {
+        match *self {
+            Precision::Nanoseconds => 1,
+            Precision::Microseconds => 1_000,
+            Precision::Milliseconds => 1_000_000,
+            Precision::Seconds => 1_000_000_000,
+            Precision::Minutes => 60 * 1_000_000_000,
+            Precision::Hours => 60 * 60 * 1_000_000_000,
+        }
+    }
+
+    /// Converts `time` units since the UNIX epoch into a `SystemTime`,
+    /// or `None` if it can't be represented.
+    pub fn system_time(&self, time: i64) -> Option<SystemTime> {
+        const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+        // `wrapping_abs` leaves `i64::MIN` alone, which is still right
+        // once reinterpreted as unsigned
+        let magnitude = time.wrapping_abs() as u64;
+        let unit = self.nanoseconds();
+        let duration = if unit >= NANOSECONDS_PER_SECOND {
+            Duration::from_secs(magnitude.checked_mul(unit / NANOSECONDS_PER_SECOND)?)
+        } else {
+            let per_second = NANOSECONDS_PER_SECOND / unit;
+            let nanoseconds = (magnitude % per_second) * unit;
+            Duration::new(magnitude / per_second, nanoseconds as u32)
+        };
+
+        if time < 0 {
+            UNIX_EPOCH.checked_sub(duration)
+        } else {
+            UNIX_EPOCH.checked_add(duration)
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -274,48 +761,244 @@ pub struct InfluxServerError {
     pub error: String,
 }
 
-pub struct AsyncUdpDb {
-    handle: Handle,
-    my_addr: SocketAddr,
+/// A 1500 byte Ethernet MTU, less the IPv6 and UDP headers. Larger
+/// datagrams would be fragmented, which InfluxDB doesn't reassemble
+/// reliably.
+pub const DEFAULT_MAX_PAYLOAD_SIZE: usize = 1500 - 40 - 8;
+
+/// Sends data to InfluxDB's UDP service.
+///
+/// A single socket is bound when the `AsyncUdpDb` is created and is
+/// shared by every `add_data` call, including ones still in flight.
+///
+/// Batches are split into as many datagrams as needed to keep each
+/// one within the maximum payload size, breaking only between points.
+///
+/// Datagrams are sent over UDP by default; `S` can be any
+/// `DatagramSocket` addressed by `SocketAddr`, such as
+/// `mock::RecordingSocket`.
+pub struct AsyncUdpDb<S = UdpSocket> {
+    socket: Rc<S>,
     their_addr: SocketAddr,
+    max_payload_size: usize,
+    /// Serialization buffers handed back by completed sends
+    buffers: Rc<RefCell<Vec<Vec<u8>>>>,
 }
 
 impl AsyncUdpDb {
+    /// Binds to an ephemeral port on all interfaces, using IPv6 if
+    /// `ip_port` is an IPv6 address and IPv4 otherwise.
     pub fn new(handle: Handle, ip_port: &str) -> Result<Self> {
+        let their_addr: SocketAddr = ip_port.parse()?;
+        let unspecified = match their_addr {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+        };
+        AsyncUdpDb::bind(handle, SocketAddr::new(unspecified, 0), their_addr)
+    }
+
+    /// Binds to `local_ip_port` (such as `"10.0.0.5:0"` or `"[::1]:0"`)
+    /// instead of letting the operating system choose.
+    pub fn with_local_addr(handle: Handle, local_ip_port: &str, ip_port: &str) -> Result<Self> {
+        AsyncUdpDb::bind(handle, local_ip_port.parse()?, ip_port.parse()?)
+    }
+
+    fn bind(handle: Handle, my_addr: SocketAddr, their_addr: SocketAddr) -> Result<Self> {
+        let socket = UdpSocket::bind(&my_addr, &handle).map_err(Error::Udp)?;
+
         Ok(AsyncUdpDb {
-            handle: handle,
-            my_addr: "0.0.0.0:0".parse()?,
+            socket: Rc::new(socket),
+            their_addr: their_addr,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            buffers: Rc::new(RefCell::new(Vec::new())),
+        })
+    }
+
+    /// The address the socket is bound to
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr().map_err(Error::Udp)
+    }
+}
+
+impl<S> AsyncUdpDb<S>
+    where S: DatagramSocket<Addr = SocketAddr> + 'static
+{
+    /// Sends to `ip_port` through `socket`
+    pub fn with_socket(socket: S, ip_port: &str) -> Result<Self> {
+        Ok(AsyncUdpDb {
+            socket: Rc::new(socket),
             their_addr: ip_port.parse()?,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            buffers: Rc::new(RefCell::new(Vec::new())),
         })
     }
 
+    pub fn max_payload_size(&self) -> usize {
+        self.max_payload_size
+    }
+
+    /// Sets the largest datagram that will be sent, in bytes. Defaults
+    /// to `DEFAULT_MAX_PAYLOAD_SIZE`.
+    pub fn set_max_payload_size(&mut self, max_payload_size: usize) {
+        self.max_payload_size = max_payload_size;
+    }
+
     pub fn add_data<T>(&self, measure: T) -> AddDataUdp
         where T: Measurement
     {
-        let mut bytes_to_send = String::new();
-        measure.to_data(&mut bytes_to_send);
-
-        // TODO: We could consume self like `send_dgram` does, which
-        // allows reusing the same socket over and over. The API would
-        // be more annoying, but might be like other futures...
-        let f = match UdpSocket::bind(&self.my_addr, &self.handle) {
-            Ok(socket) => {
-                let f = socket.send_dgram(bytes_to_send, self.their_addr)
-                    .map(|_| ())
-                    .map_err(Error::Udp);
-                Either::A(f)
-            }
-            Err(e) => {
-                Either::B(futures::future::err(Error::Udp(e)))
+        AddDataUdp(send_datagrams(&self.socket, self.their_addr, self.max_payload_size, &self.buffers, measure))
+    }
+}
+
+/// How many serialization buffers a datagram client keeps around for
+/// reuse
+const MAX_SPARE_BUFFERS: usize = 8;
+
+/// Serializes `measure` into a spare buffer and sends it through the
+/// shared socket, split to fit `max_payload_size`.
+fn send_datagrams<S, T>(socket: &Rc<S>,
+                        their_addr: S::Addr,
+                        max_payload_size: usize,
+                        buffers: &Rc<RefCell<Vec<Vec<u8>>>>,
+                        measure: T)
+                        -> Box<Future<Item = (), Error = Error>>
+    where S: DatagramSocket + 'static,
+          T: Measurement,
+{
+    let mut bytes_to_send = buffers.borrow_mut().pop().unwrap_or_else(Vec::new);
+    bytes_to_send.clear();
+    let datagrams = measure.to_bytes(&mut bytes_to_send)
+        .and_then(|_| split_datagrams(&bytes_to_send, max_payload_size));
+
+    let datagrams = match datagrams {
+        Ok(datagrams) => datagrams,
+        Err(e) => {
+            return_buffer(buffers, bytes_to_send);
+            return Box::new(futures::future::err(e));
+        }
+    };
+
+    Box::new(SendDatagrams {
+        socket: socket.clone(),
+        their_addr: their_addr,
+        buffer: Some(bytes_to_send),
+        datagrams: datagrams.into_iter(),
+        buffers: buffers.clone(),
+    })
+}
+
+fn return_buffer(buffers: &RefCell<Vec<Vec<u8>>>, buffer: Vec<u8>) {
+    let mut buffers = buffers.borrow_mut();
+    if buffers.len() < MAX_SPARE_BUFFERS {
+        buffers.push(buffer);
+    }
+}
+
+/// Divides line protocol data into ranges of at most
+/// `max_payload_size` bytes, breaking only at the end of a line.
+fn split_datagrams(data: &[u8], max_payload_size: usize) -> Result<Vec<Range<usize>>> {
+    let mut datagrams = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+
+    let mut line_start = 0;
+    while line_start < data.len() {
+        let line_end = data[line_start..].iter().position(|&b| b == b'\n')
+            .map_or(data.len(), |i| line_start + i);
+
+        if line_end - line_start > max_payload_size {
+            return Err(Error::PointTooLarge(line_end - line_start, max_payload_size));
+        }
+
+        if line_end - start > max_payload_size {
+            datagrams.push(start..end);
+            start = line_start;
+        }
+
+        // Keep the newline too, unless that would make the datagram too big
+        let with_newline = cmp::min(line_end + 1, data.len());
+        end = if with_newline - start <= max_payload_size { with_newline } else { line_end };
+
+        line_start = line_end + 1;
+    }
+
+    if start < end {
+        datagrams.push(start..end);
+    }
+
+    Ok(datagrams)
+}
+
+/// A socket that datagrams can be sent from without connecting it
+pub trait DatagramSocket {
+    type Addr: 'static;
+
+    /// Sends `buf` as one datagram, or fails with `WouldBlock` after
+    /// arranging for the current task to be notified when it can be sent
+    fn send_datagram(&self, buf: &[u8], addr: &Self::Addr) -> io::Result<usize>;
+
+    /// Wraps errors from `send_datagram`
+    fn error(error: io::Error) -> Error;
+}
+
+impl DatagramSocket for UdpSocket {
+    type Addr = SocketAddr;
+
+    fn send_datagram(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        self.send_to(buf, addr)
+    }
+
+    fn error(error: io::Error) -> Error {
+        Error::Udp(error)
+    }
+}
+
+/// Like `UdpSocket::send_dgram`, but borrows the shared socket
+/// instead of taking ownership of it, and sends each range of the
+/// buffer as its own datagram.
+struct SendDatagrams<S>
+    where S: DatagramSocket
+{
+    socket: Rc<S>,
+    their_addr: S::Addr,
+    buffer: Option<Vec<u8>>,
+    datagrams: vec::IntoIter<Range<usize>>,
+    buffers: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+impl<S> Future for SendDatagrams<S>
+    where S: DatagramSocket
+{
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        while let Some(datagram) = self.datagrams.as_slice().first().cloned() {
+            let buffer = self.buffer.as_ref().expect("Polled SendDatagrams after completion");
+            let payload = &buffer[datagram];
+            match self.socket.send_datagram(payload, &self.their_addr) {
+                Ok(sent) if sent == payload.len() => {}
+                Ok(_) => {
+                    let e = io::Error::new(io::ErrorKind::Other, "Unable to send the entire datagram");
+                    return Err(S::error(e));
+                }
+                // The socket has arranged for us to be polled again once
+                // it is writable
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(e) => return Err(S::error(e)),
             }
-        };
 
-        AddDataUdp(f.boxed())
+            self.datagrams.next();
+        }
+
+        let buffer = self.buffer.take().expect("Polled SendDatagrams after completion");
+        return_buffer(&self.buffers, buffer);
+        Ok(Async::Ready(()))
     }
 }
 
 #[must_use = "futures do nothing unless polled"]
-pub struct AddDataUdp(BoxFuture<(), Error>);
+pub struct AddDataUdp(Box<Future<Item = (), Error = Error>>);
 
 impl Future for AddDataUdp {
     type Item = ();
@@ -325,3 +1008,124 @@ impl Future for AddDataUdp {
         self.0.poll()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::{self, Value};
+
+    use super::{requires_post, Error, Precision, QueryResponse, Series};
+
+    #[test]
+    fn reads_are_not_posted() {
+        assert!(!requires_post("SELECT * FROM cpu"));
+        assert!(!requires_post("select value from cpu where host = 'into'"));
+        assert!(!requires_post("SHOW DATABASES"));
+        assert!(!requires_post(""));
+    }
+
+    #[test]
+    fn changes_are_posted() {
+        assert!(requires_post("CREATE DATABASE mydb"));
+        assert!(requires_post("drop measurement cpu"));
+        assert!(requires_post("  DELETE FROM cpu"));
+        assert!(requires_post("SELECT mean(value) INTO cpu_1h FROM cpu GROUP BY time(1h)"));
+        assert!(requires_post("GRANT ALL TO admin"));
+        assert!(requires_post("ALTER RETENTION POLICY default ON mydb DURATION 1d"));
+        assert!(requires_post("KILL QUERY 36"));
+        assert!(requires_post("REVOKE ALL FROM admin"));
+    }
+
+    #[test]
+    fn any_change_in_a_batch_is_posted() {
+        assert!(requires_post("SELECT * FROM cpu; DROP SERIES FROM cpu"));
+    }
+
+    fn series(columns: &[&str], times: Vec<Value>) -> Series {
+        Series {
+            name: String::from("cpu"),
+            columns: columns.iter().map(|&c| String::from(c)).collect(),
+            values: times.into_iter().map(|time| vec![time, Value::from(0.5)]).collect(),
+            epoch: None,
+        }
+    }
+
+    #[test]
+    fn epoch_times_are_decoded() {
+        let mut series = series(&["time", "value"], vec![Value::from(1434055562i64), Value::from(-1i64)]);
+        series.epoch = Some(Precision::Seconds);
+
+        assert_eq!(series.epoch_times().unwrap(), vec![1434055562, -1]);
+        assert_eq!(series.system_times().unwrap(),
+                   vec![UNIX_EPOCH + Duration::from_secs(1434055562),
+                        UNIX_EPOCH - Duration::from_secs(1)]);
+    }
+
+    #[test]
+    fn times_are_not_decoded_without_an_epoch() {
+        let series = series(&["time", "value"], vec![Value::from(1434055562i64)]);
+
+        match series.system_times() {
+            Err(Error::TimeColumn(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn responses_record_the_epoch_they_asked_for() {
+        let response: QueryResponse =
+            serde_json::from_str(r#"{"results":[{"statement_id":0,"series":[{"name":"cpu","columns":["time"],
+                                                                             "values":[[1]]}]}]}"#)
+            .unwrap();
+        let response = response.with_epoch(Some(Precision::Milliseconds));
+
+        assert_eq!(response.results[0].series[0].epoch, Some(Precision::Milliseconds));
+        assert_eq!(response.results[0].series[0].system_times().unwrap(),
+                   vec![UNIX_EPOCH + Duration::from_millis(1)]);
+    }
+
+    #[test]
+    fn rfc3339_times_are_not_epoch_times() {
+        let series = series(&["time", "value"], vec![Value::from("2015-06-11T20:46:02Z")]);
+
+        match series.epoch_times() {
+            Err(Error::TimeColumn(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_time_column_is_required() {
+        let series = series(&["host", "value"], vec![Value::from(1i64)]);
+
+        match series.epoch_times() {
+            Err(Error::TimeColumn(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn every_precision_converts_to_system_time() {
+        let cases = [
+            (Precision::Nanoseconds, Duration::new(1, 500_000_000)),
+            (Precision::Microseconds, Duration::new(1_500, 0)),
+            (Precision::Milliseconds, Duration::new(1_500_000, 0)),
+            (Precision::Seconds, Duration::from_secs(1_500_000_000)),
+            (Precision::Minutes, Duration::from_secs(1_500_000_000 * 60)),
+            (Precision::Hours, Duration::from_secs(1_500_000_000 * 60 * 60)),
+        ];
+
+        for &(precision, duration) in &cases {
+            assert_eq!(precision.system_time(1_500_000_000), Some(UNIX_EPOCH + duration), "{:?}", precision);
+        }
+
+        assert_eq!(Precision::Milliseconds.system_time(-1_500),
+                   Some(UNIX_EPOCH - Duration::new(1, 500_000_000)));
+    }
+
+    #[test]
+    fn times_beyond_system_time_are_rejected() {
+        assert_eq!(Precision::Hours.system_time(::std::i64::MAX), None);
+    }
+}
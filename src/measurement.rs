@@ -1,8 +1,33 @@
-use std::fmt::Write;
-use std::time::{self, SystemTime};
+use std::fmt::{self, Write as FmtWrite};
+use std::io::{self, Write};
+use std::time::{self, Duration, SystemTime};
+
+use {Error, Result};
 
 pub trait Measurement {
     fn to_data(&self, &mut String);
+
+    /// Like `to_data`, but reports values that can't be represented in
+    /// the line protocol as errors instead of panicking.
+    ///
+    /// When an error is returned, part of the measurement may already
+    /// have been appended to `data`.
+    fn try_to_data(&self, data: &mut String) -> Result<()> {
+        self.to_data(data);
+        Ok(())
+    }
+
+    /// Appends the measurement in the line protocol to a byte buffer.
+    ///
+    /// The buffer can be cleared and reused between measurements to
+    /// avoid reallocating. When an error is returned, part of the
+    /// measurement may already have been appended to `data`.
+    fn to_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        let mut s = String::new();
+        self.try_to_data(&mut s)?;
+        data.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
 }
 
 impl<'a, T> Measurement for &'a T
@@ -11,6 +36,14 @@ impl<'a, T> Measurement for &'a T
     fn to_data(&self, bytes: &mut String) {
         (**self).to_data(bytes)
     }
+
+    fn try_to_data(&self, bytes: &mut String) -> Result<()> {
+        (**self).try_to_data(bytes)
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        (**self).to_bytes(bytes)
+    }
 }
 
 impl<T> Measurement for Box<T>
@@ -19,6 +52,14 @@ impl<T> Measurement for Box<T>
     fn to_data(&self, bytes: &mut String) {
         (**self).to_data(bytes)
     }
+
+    fn try_to_data(&self, bytes: &mut String) -> Result<()> {
+        (**self).try_to_data(bytes)
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        (**self).to_bytes(bytes)
+    }
 }
 
 impl<T> Measurement for [T]
@@ -30,6 +71,22 @@ impl<T> Measurement for [T]
             bytes.push_str("\n");
         }
     }
+
+    fn try_to_data(&self, bytes: &mut String) -> Result<()> {
+        for item in self.iter() {
+            item.try_to_data(bytes)?;
+            bytes.push_str("\n");
+        }
+        Ok(())
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        for item in self.iter() {
+            item.to_bytes(bytes)?;
+            bytes.push(b'\n');
+        }
+        Ok(())
+    }
 }
 
 impl<T> Measurement for Vec<T>
@@ -38,64 +95,230 @@ impl<T> Measurement for Vec<T>
     fn to_data(&self, bytes: &mut String) {
         self[..].to_data(bytes)
     }
+
+    fn try_to_data(&self, bytes: &mut String) -> Result<()> {
+        self[..].try_to_data(bytes)
+    }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        self[..].to_bytes(bytes)
+    }
 }
 
 impl<'a> Measurement for &'a str {
     fn to_data(&self, bytes: &mut String) {
         bytes.push_str(self);
     }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        bytes.extend_from_slice(self.as_bytes());
+        Ok(())
+    }
 }
 
 impl Measurement for String {
     fn to_data(&self, bytes: &mut String) {
         self.as_str().to_data(bytes)
     }
+
+    fn to_bytes(&self, bytes: &mut Vec<u8>) -> Result<()> {
+        self.as_str().to_bytes(bytes)
+    }
+}
+
+/// Serializes `measure` into `buffer` and writes it to `writer` with a
+/// single `write_all`, so that files, sockets and compressors aren't
+/// handed many small writes.
+///
+/// `buffer` is cleared first; reuse it across calls to avoid
+/// reallocating.
+pub fn write<M, W>(measure: &M, writer: &mut W, buffer: &mut Vec<u8>) -> Result<()>
+    where M: ?Sized + Measurement,
+          W: io::Write,
+{
+    buffer.clear();
+    measure.to_bytes(buffer)?;
+    writer.write_all(buffer).map_err(Error::Io)
+}
+
+/// Characters that need a backslash before them in measurement names
+pub const MEASUREMENT_SPECIAL_CHARACTERS: &'static [char] = &[',', ' '];
+/// Characters that need a backslash before them in tag keys, tag
+/// values and field keys
+pub const KEY_SPECIAL_CHARACTERS: &'static [char] = &[',', '=', ' '];
+/// Characters that need a backslash before them in string field values
+pub const STRING_SPECIAL_CHARACTERS: &'static [char] = &['"', '\\'];
+
+/// Appends `value`, adding a backslash before each of the `special`
+/// characters.
+///
+/// The line protocol has no way to escape line breaks, so values
+/// containing them are rejected.
+pub fn escape(data: &mut String, value: &str, special: &[char]) -> Result<()> {
+    escape_pieces(value, special, |piece| data.push_str(piece))
+}
+
+/// Like `escape`, but appends to a byte buffer
+pub fn escape_bytes(data: &mut Vec<u8>, value: &str, special: &[char]) -> Result<()> {
+    escape_pieces(value, special, |piece| data.extend_from_slice(piece.as_bytes()))
+}
+
+/// Passes `value` to `push` a piece at a time, with backslashes between
+/// them, so that `String`s and byte buffers can share the escaping
+fn escape_pieces<F>(value: &str, special: &[char], mut push: F) -> Result<()>
+    where F: FnMut(&str)
+{
+    if value.contains(|c| c == '\n' || c == '\r') {
+        return Err(Error::Unescapable(value.into()));
+    }
+
+    let mut unescaped_from = 0;
+    for (i, c) in value.char_indices() {
+        if special.contains(&c) {
+            push(&value[unescaped_from..i]);
+            push("\\");
+            unescaped_from = i;
+        }
+    }
+    push(&value[unescaped_from..]);
+
+    Ok(())
+}
+
+/// Escapes everything written through it as a tag value
+struct Escaper<F> {
+    push: F,
+}
+
+impl<F> fmt::Write for Escaper<F>
+    where F: FnMut(&str)
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        escape_pieces(s, KEY_SPECIAL_CHARACTERS, &mut self.push).map_err(|_| fmt::Error)
+    }
+}
+
+fn append_display<T, F>(value: &T, push: F) -> Result<()>
+    where T: ?Sized + fmt::Display,
+          F: FnMut(&str),
+{
+    let mut escaper = Escaper { push: push };
+    write!(escaper, "{}", value).map_err(|_| Error::Unescapable(value.to_string()))
 }
 
-pub struct Tag<'a> {
+pub struct Tag<'a, T: ?Sized + 'a> {
     name: &'a str,
-    value: &'a str,
+    value: &'a T,
 }
 
-impl<'a> Tag<'a> {
-    /// The name and value are not currently escaped
-    pub fn new(name: &'a str, value: &'a str) -> Tag<'a> {
+impl<'a, T> Tag<'a, T>
+    where T: ?Sized + TagValue + 'a
+{
+    pub fn new(name: &'a str, value: &'a T) -> Tag<'a, T> {
         Tag { name: name, value: value }
     }
 
-    pub fn append(&self, data: &mut String) {
-        data.push_str(self.name);
-        data.push_str("=");
-        data.push_str(self.value);
+    pub fn append(&self, data: &mut String) -> Result<()> {
+        escape(data, self.name, KEY_SPECIAL_CHARACTERS)?;
+        data.push('=');
+        self.value.append(data)
+    }
+
+    /// Like `append`, but appends to a byte buffer
+    pub fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        escape_bytes(data, self.name, KEY_SPECIAL_CHARACTERS)?;
+        data.push(b'=');
+        self.value.append_bytes(data)
+    }
+}
+
+/// A value that can be used for a tag.
+///
+/// InfluxDB stores every tag value as a string, so anything that
+/// implements `Display` (strings, integers, booleans, `IpAddr`, your
+/// own enums...) can be used. Types that don't implement `Display`
+/// can implement this trait directly, using `escape` with
+/// `KEY_SPECIAL_CHARACTERS`.
+pub trait TagValue {
+    fn append(&self, &mut String) -> Result<()>;
+
+    /// Like `append`, but appends to a byte buffer. Implement this too
+    /// to avoid the intermediate `String`.
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        let mut s = String::new();
+        self.append(&mut s)?;
+        data.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl<T> TagValue for T
+    where T: ?Sized + fmt::Display,
+{
+    fn append(&self, data: &mut String) -> Result<()> {
+        append_display(self, |piece| data.push_str(piece))
+    }
+
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        append_display(self, |piece| data.extend_from_slice(piece.as_bytes()))
     }
 }
 
 pub trait FieldValue {
-    fn append(&self, &mut String);
+    fn append(&self, &mut String) -> Result<()>;
+
+    /// Like `append`, but appends to a byte buffer. Implement this too
+    /// to avoid the intermediate `String`.
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        let mut s = String::new();
+        self.append(&mut s)?;
+        data.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
 }
 
 impl<'a, T> FieldValue for &'a T
     where T: ?Sized + FieldValue,
 {
-    fn append(&self, data: &mut String) {
+    fn append(&self, data: &mut String) -> Result<()> {
         (**self).append(data)
     }
+
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        (**self).append_bytes(data)
+    }
 }
 
 impl<T> FieldValue for Box<T>
     where T: ?Sized + FieldValue,
 {
-    fn append(&self, data: &mut String) {
+    fn append(&self, data: &mut String) -> Result<()> {
         (**self).append(data)
     }
+
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        (**self).append_bytes(data)
+    }
 }
 
 macro_rules! floating_point_field {
     ($($typ: ty),* ) => {
         $(
         impl FieldValue for $typ {
-            fn append(&self, data: &mut String) {
-                write!(data, "{}", self).expect("Unable to write floating point number")
+            fn append(&self, data: &mut String) -> Result<()> {
+                // InfluxDB has no representation for NaN or infinity
+                if !self.is_finite() {
+                    return Err(Error::NonFiniteFloat(*self as f64));
+                }
+                write!(data, "{}", self)?;
+                Ok(())
+            }
+
+            fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+                if !self.is_finite() {
+                    return Err(Error::NonFiniteFloat(*self as f64));
+                }
+                write!(data, "{}", self).map_err(Error::Io)
             }
         }
         )*
@@ -108,8 +331,13 @@ macro_rules! integer_field {
     ($($typ: ty),* ) => {
         $(
         impl FieldValue for $typ {
-            fn append(&self, data: &mut String) {
-                write!(data, "{}i", self).expect("Unable to write integral number")
+            fn append(&self, data: &mut String) -> Result<()> {
+                write!(data, "{}i", self)?;
+                Ok(())
+            }
+
+            fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+                write!(data, "{}i", self).map_err(Error::Io)
             }
         }
         )*
@@ -120,25 +348,40 @@ macro_rules! integer_field {
 integer_field!(i8, i16, i32, i64, u8, u16, u32);
 
 impl FieldValue for bool {
-    fn append(&self, data: &mut String) {
-        if *self {
-            data.push_str("T");
-        } else {
-            data.push_str("F");
-        }
+    fn append(&self, data: &mut String) -> Result<()> {
+        data.push(if *self { 'T' } else { 'F' });
+        Ok(())
+    }
+
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        data.push(if *self { b'T' } else { b'F' });
+        Ok(())
     }
 }
 
-// TODO: escaping of values
 impl FieldValue for str {
-    fn append(&self, data: &mut String) {
-        write!(data, r#""{}""#, self).expect("Unable to write string")
+    fn append(&self, data: &mut String) -> Result<()> {
+        data.push('"');
+        escape(data, self, STRING_SPECIAL_CHARACTERS)?;
+        data.push('"');
+        Ok(())
+    }
+
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        data.push(b'"');
+        escape_bytes(data, self, STRING_SPECIAL_CHARACTERS)?;
+        data.push(b'"');
+        Ok(())
     }
 }
 
 impl FieldValue for String {
-    fn append(&self, data: &mut String) {
-        self.as_str().append(data)
+    fn append(&self, data: &mut String) -> Result<()> {
+        FieldValue::append(self.as_str(), data)
+    }
+
+    fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        FieldValue::append_bytes(self.as_str(), data)
     }
 }
 
@@ -150,36 +393,137 @@ pub struct Field<'a, T: 'a> {
 impl<'a, T> Field<'a, T>
     where T: FieldValue + 'a
 {
-    /// The name and value are not currently escaped
     pub fn new(name: &'a str, value: &'a T) -> Field<'a, T> {
         Field { name: name, value: value }
     }
 
-    pub fn append(&self, data: &mut String) {
-        data.push_str(self.name);
-        data.push_str("=");
+    pub fn append(&self, data: &mut String) -> Result<()> {
+        escape(data, self.name, KEY_SPECIAL_CHARACTERS)?;
+        data.push('=');
         self.value.append(data)
     }
+
+    /// Like `append`, but appends to a byte buffer
+    pub fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        escape_bytes(data, self.name, KEY_SPECIAL_CHARACTERS)?;
+        data.push(b'=');
+        self.value.append_bytes(data)
+    }
 }
 
-pub struct Timestamp<'a> {
-    value: &'a SystemTime,
+pub struct Timestamp<'a, T: ?Sized + 'a> {
+    value: &'a T,
 }
 
-impl<'a> Timestamp<'a> {
-    pub fn new(time: &SystemTime) -> Timestamp {
+impl<'a, T> Timestamp<'a, T>
+    where T: ?Sized + TimestampValue + 'a
+{
+    pub fn new(time: &'a T) -> Timestamp<'a, T> {
         Timestamp { value: time }
     }
 
-    pub fn append(&self, data: &mut String) {
-        const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+    pub fn append(&self, data: &mut String) -> Result<()> {
+        let timestamp = self.value.nanoseconds()?;
+        write!(data, "{}", timestamp)?;
+        Ok(())
+    }
+
+    /// Like `append`, but appends to a byte buffer
+    pub fn append_bytes(&self, data: &mut Vec<u8>) -> Result<()> {
+        let timestamp = self.value.nanoseconds()?;
+        write!(data, "{}", timestamp).map_err(Error::Io)
+    }
+}
+
+/// The earliest and latest timestamps InfluxDB accepts, in nanoseconds
+/// since the UNIX epoch (1677-09-21T00:12:43.145224194Z and
+/// 2262-04-11T23:47:16.854775806Z).
+pub const MIN_NANOSECONDS: i64 = ::std::i64::MIN + 2;
+pub const MAX_NANOSECONDS: i64 = ::std::i64::MAX - 1;
+
+const NANOSECONDS_PER_SECOND: i64 = 1_000_000_000;
 
-        let duration = self.value.duration_since(time::UNIX_EPOCH)
-            .expect("Timestamp must come after the UNIX epoch");
-        let seconds_as_nanoseconds = duration.as_secs() * NANOSECONDS_PER_SECOND;
-        // Truncating from u64 to i64 shouldn't impact us for a long time
-        let timestamp = seconds_as_nanoseconds as i64 + duration.subsec_nanos() as i64;
-        write!(data, "{}", timestamp).expect("Unable to write timestamp");
+/// A value that can be used as the timestamp of a measurement.
+pub trait TimestampValue {
+    /// The number of nanoseconds since the UNIX epoch; negative values
+    /// are before it.
+    fn nanoseconds(&self) -> Result<i64>;
+}
+
+impl<'a, T> TimestampValue for &'a T
+    where T: ?Sized + TimestampValue,
+{
+    fn nanoseconds(&self) -> Result<i64> {
+        (**self).nanoseconds()
+    }
+}
+
+impl<T> TimestampValue for Box<T>
+    where T: ?Sized + TimestampValue,
+{
+    fn nanoseconds(&self) -> Result<i64> {
+        (**self).nanoseconds()
+    }
+}
+
+/// Already nanoseconds since the UNIX epoch
+impl TimestampValue for i64 {
+    fn nanoseconds(&self) -> Result<i64> {
+        check_nanoseconds(Some(*self))
+    }
+}
+
+/// The time elapsed since the UNIX epoch
+impl TimestampValue for Duration {
+    fn nanoseconds(&self) -> Result<i64> {
+        duration_nanoseconds(self)
+    }
+}
+
+impl TimestampValue for SystemTime {
+    fn nanoseconds(&self) -> Result<i64> {
+        match self.duration_since(time::UNIX_EPOCH) {
+            Ok(after) => duration_nanoseconds(&after),
+            Err(e) => {
+                let before = duration_nanoseconds(&e.duration())?;
+                check_nanoseconds(Some(-before))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz> TimestampValue for ::chrono::DateTime<Tz>
+    where Tz: ::chrono::TimeZone,
+{
+    fn nanoseconds(&self) -> Result<i64> {
+        seconds_and_nanoseconds(self.timestamp(), self.timestamp_subsec_nanos() as i64)
+    }
+}
+
+#[cfg(feature = "time")]
+impl TimestampValue for ::time::Timespec {
+    fn nanoseconds(&self) -> Result<i64> {
+        seconds_and_nanoseconds(self.sec, self.nsec as i64)
+    }
+}
+
+fn duration_nanoseconds(duration: &Duration) -> Result<i64> {
+    if duration.as_secs() > ::std::i64::MAX as u64 {
+        return Err(Error::TimestampOutOfRange);
+    }
+    seconds_and_nanoseconds(duration.as_secs() as i64, duration.subsec_nanos() as i64)
+}
+
+fn seconds_and_nanoseconds(seconds: i64, nanoseconds: i64) -> Result<i64> {
+    check_nanoseconds(seconds.checked_mul(NANOSECONDS_PER_SECOND)
+                      .and_then(|s| s.checked_add(nanoseconds)))
+}
+
+fn check_nanoseconds(nanoseconds: Option<i64>) -> Result<i64> {
+    match nanoseconds {
+        Some(n) if n >= MIN_NANOSECONDS && n <= MAX_NANOSECONDS => Ok(n),
+        _ => Err(Error::TimestampOutOfRange),
     }
 }
 
@@ -187,6 +531,27 @@ impl<'a> Timestamp<'a> {
 mod test {
     use super::*;
 
+    #[test]
+    fn batches_can_be_serialized_to_bytes() {
+        let batch = vec!["cpu value=1", "cpu value=2"];
+        let mut bytes = Vec::new();
+
+        batch.to_bytes(&mut bytes).unwrap();
+        assert_eq!(bytes, b"cpu value=1\ncpu value=2\n");
+    }
+
+    #[test]
+    fn measurements_can_be_written_to_io_writers() {
+        let mut buffer = Vec::new();
+        let mut output = Vec::new();
+
+        write(&"cpu value=1\n", &mut output, &mut buffer).unwrap();
+        write(&String::from("cpu value=2\n"), &mut output, &mut buffer).unwrap();
+
+        assert_eq!(output, b"cpu value=1\ncpu value=2\n");
+        assert_eq!(buffer, b"cpu value=2\n");
+    }
+
     #[test]
     fn f32_fields_can_be_serialized() {
         assert_eq!(field(3.4f32), "3.4");
@@ -238,6 +603,107 @@ mod test {
         assert_eq!(field(false), "F");
     }
 
+    #[test]
+    fn string_fields_are_quoted_and_escaped() {
+        assert_eq!(field("us-west"), r#""us-west""#);
+        assert_eq!(field(r#"say "hi" \ bye"#), r#""say \"hi\" \\ bye""#);
+    }
+
+    #[test]
+    fn non_finite_floats_are_errors() {
+        let mut s = Vec::new();
+
+        match FieldValue::append_bytes(&::std::f64::NAN, &mut s) {
+            Err(Error::NonFiniteFloat(v)) => assert!(v.is_nan()),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        match FieldValue::append_bytes(&::std::f32::INFINITY, &mut s) {
+            Err(Error::NonFiniteFloat(v)) => assert_eq!(v, ::std::f64::INFINITY),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tag_and_field_keys_and_values_are_escaped() {
+        let mut s = Vec::new();
+        Tag::new("the region", "us west,1=2").append_bytes(&mut s).unwrap();
+        assert_eq!(s, br"the\ region=us\ west\,1\=2");
+
+        let mut s = Vec::new();
+        Field::new("a,b=c d", &1i32).append_bytes(&mut s).unwrap();
+        assert_eq!(s, br"a\,b\=c\ d=1i");
+    }
+
+    #[test]
+    fn strings_and_byte_buffers_get_the_same_line_protocol() {
+        let mut s = String::new();
+        Tag::new("the region", "us west").append(&mut s).unwrap();
+        s.push(' ');
+        Field::new("note", &"say \"hi\"").append(&mut s).unwrap();
+        s.push(',');
+        Field::new("value", &1.5f64).append(&mut s).unwrap();
+        s.push(' ');
+        Timestamp::new(&5i64).append(&mut s).unwrap();
+
+        let mut bytes = Vec::new();
+        Tag::new("the region", "us west").append_bytes(&mut bytes).unwrap();
+        bytes.push(b' ');
+        Field::new("note", &"say \"hi\"").append_bytes(&mut bytes).unwrap();
+        bytes.push(b',');
+        Field::new("value", &1.5f64).append_bytes(&mut bytes).unwrap();
+        bytes.push(b' ');
+        Timestamp::new(&5i64).append_bytes(&mut bytes).unwrap();
+
+        assert_eq!(s, r#"the\ region=us\ west note="say \"hi\"",value=1.5 5"#);
+        assert_eq!(s.as_bytes(), &bytes[..]);
+    }
+
+    #[test]
+    fn line_breaks_cannot_be_escaped() {
+        let mut s = Vec::new();
+
+        match Tag::new("region", "us\nwest").append_bytes(&mut s) {
+            Err(Error::Unescapable(v)) => assert_eq!(v, "us\nwest"),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        match Field::new("value\r", &1i32).append_bytes(&mut s) {
+            Err(Error::Unescapable(v)) => assert_eq!(v, "value\r"),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        match FieldValue::append_bytes("a\nb", &mut s) {
+            Err(Error::Unescapable(v)) => assert_eq!(v, "a\nb"),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn string_tags_can_be_serialized() {
+        assert_eq!(tag("us-west"), "region=us-west");
+        assert_eq!(tag(String::from("us-west")), "region=us-west");
+    }
+
+    #[test]
+    fn integer_tags_can_be_serialized() {
+        assert_eq!(tag(42u64), "region=42");
+        assert_eq!(tag(-42i8), "region=-42");
+    }
+
+    #[test]
+    fn boolean_tags_can_be_serialized() {
+        assert_eq!(tag(true), "region=true");
+    }
+
+    #[test]
+    fn ip_address_tags_can_be_serialized() {
+        use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+        assert_eq!(tag(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))), "region=10.0.0.1");
+        assert_eq!(tag(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), "region=::1");
+    }
+
     #[test]
     fn timestamps_can_be_serialized() {
         let s = timestamp(SystemTime::now());
@@ -247,17 +713,79 @@ mod test {
         assert_eq!(s.len(), 19);
     }
 
+    #[test]
+    fn timestamps_before_the_epoch_can_be_serialized() {
+        let before = time::UNIX_EPOCH - Duration::new(1, 500);
+        assert_eq!(timestamp(before), "-1000000500");
+    }
+
+    #[test]
+    fn integer_timestamps_can_be_serialized() {
+        assert_eq!(timestamp(1434055562000000000i64), "1434055562000000000");
+        assert_eq!(timestamp(-1i64), "-1");
+    }
+
+    #[test]
+    fn duration_timestamps_can_be_serialized() {
+        assert_eq!(timestamp(Duration::new(1434055562, 5)), "1434055562000000005");
+    }
+
+    #[test]
+    fn out_of_range_timestamps_are_errors() {
+        let mut s = Vec::new();
+
+        let err = Timestamp::new(&Duration::from_secs(10_000_000_000)).append_bytes(&mut s);
+        assert!(matches_out_of_range(err));
+
+        let err = Timestamp::new(&::std::i64::MAX).append_bytes(&mut s);
+        assert!(matches_out_of_range(err));
+
+        assert!(s.is_empty());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_timestamps_can_be_serialized() {
+        use chrono::{TimeZone, Utc};
+
+        assert_eq!(timestamp(Utc.timestamp(1434055562, 5)), "1434055562000000005");
+        assert_eq!(timestamp(Utc.ymd(1969, 12, 31).and_hms(23, 59, 59)), "-1000000000");
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_timestamps_can_be_serialized() {
+        assert_eq!(timestamp(::time::Timespec::new(1434055562, 5)), "1434055562000000005");
+    }
+
+    fn matches_out_of_range(result: Result<()>) -> bool {
+        match result {
+            Err(Error::TimestampOutOfRange) => true,
+            _ => false,
+        }
+    }
+
     fn field<T>(val: T) -> String
         where T: FieldValue,
     {
-        let mut s = String::new();
-        val.append(&mut s);
-        s
+        let mut s = Vec::new();
+        val.append_bytes(&mut s).expect("Unable to serialize field");
+        String::from_utf8(s).expect("Field was not UTF-8")
     }
 
-    fn timestamp(val: SystemTime) -> String {
-        let mut s = String::new();
-        Timestamp::new(&val).append(&mut s);
-        s
+    fn tag<T>(val: T) -> String
+        where T: TagValue,
+    {
+        let mut s = Vec::new();
+        Tag::new("region", &val).append_bytes(&mut s).expect("Unable to serialize tag");
+        String::from_utf8(s).expect("Tag was not UTF-8")
+    }
+
+    fn timestamp<T>(val: T) -> String
+        where T: TimestampValue,
+    {
+        let mut s = Vec::new();
+        Timestamp::new(&val).append_bytes(&mut s).expect("Unable to serialize timestamp");
+        String::from_utf8(s).expect("Timestamp was not UTF-8")
     }
 }
@@ -0,0 +1,348 @@
+//! The small part of InfluxQL that the mock server understands
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A keyword or an unquoted identifier
+    Word(String),
+    /// A double quoted identifier
+    Quoted(String),
+    /// A single quoted string literal
+    Str(String),
+    Number(String),
+    Symbol(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    CreateDatabase(String),
+    DropDatabase(String),
+    DropMeasurement(String),
+    DropShard,
+    /// `DELETE`, and `DROP SERIES`
+    Delete(Option<String>, Vec<Condition>),
+    ShowDatabases,
+    ShowMeasurements,
+    /// The columns, or `None` for `*`
+    Select(Option<Vec<String>>, String, Vec<Condition>),
+}
+
+impl Statement {
+    /// The statement's keywords, if InfluxDB only runs it when POSTed
+    pub fn post_only(&self) -> Option<&'static str> {
+        match *self {
+            Statement::CreateDatabase(_) => Some("CREATE DATABASE"),
+            Statement::DropDatabase(_) => Some("DROP DATABASE"),
+            Statement::DropMeasurement(_) => Some("DROP MEASUREMENT"),
+            Statement::DropShard => Some("DROP SHARD"),
+            Statement::Delete(..) => Some("DELETE"),
+            Statement::ShowDatabases | Statement::ShowMeasurements | Statement::Select(..) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    TagEquals(String, String),
+    TimeAtLeast(i64),
+    TimeAfter(i64),
+    TimeBefore(i64),
+    TimeAtMost(i64),
+}
+
+impl Condition {
+    pub fn matches(&self, tags: &::std::collections::BTreeMap<String, String>, time: i64) -> bool {
+        match *self {
+            Condition::TagEquals(ref key, ref value) => tags.get(key) == Some(value),
+            Condition::TimeAtLeast(t) => time >= t,
+            Condition::TimeAfter(t) => time > t,
+            Condition::TimeBefore(t) => time < t,
+            Condition::TimeAtMost(t) => time <= t,
+        }
+    }
+}
+
+/// Parses each of the `;` separated statements. A statement that can't
+/// be parsed gives an error message in its place.
+pub fn parse(query: &str) -> Result<Vec<Result<Statement, String>>, String> {
+    let tokens = tokenize(query)?;
+    Ok(tokens.split(|token| *token == Token::Symbol(";"))
+        .filter(|statement| !statement.is_empty())
+        .map(|statement| Parser { tokens: statement, position: 0 }.statement())
+        .collect())
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => {
+                            match chars.next() {
+                                Some('n') => value.push('\n'),
+                                Some(escaped) => value.push(escaped),
+                                None => return Err(String::from("unterminated quote")),
+                            }
+                        }
+                        Some(q) if q == c => break,
+                        Some(other) => value.push(other),
+                        None => return Err(String::from("unterminated quote")),
+                    }
+                }
+                tokens.push(if c == '"' { Token::Quoted(value) } else { Token::Str(value) });
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+                number.push(c);
+                chars.next();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_ascii_digit() {
+                        break;
+                    }
+                    number.push(d);
+                    chars.next();
+                }
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = String::new();
+                while let Some(&w) = chars.peek() {
+                    if !(w.is_alphanumeric() || w == '_' || w == '.') {
+                        break;
+                    }
+                    word.push(w);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => {
+                chars.next();
+                let symbol = match (c, chars.peek()) {
+                    ('>', Some(&'=')) => ">=",
+                    ('<', Some(&'=')) => "<=",
+                    ('!', Some(&'=')) => "!=",
+                    ('=', _) => "=",
+                    ('>', _) => ">",
+                    ('<', _) => "<",
+                    (',', _) => ",",
+                    ('*', _) => "*",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    (';', _) => ";",
+                    _ => return Err(format!("unexpected '{}'", c)),
+                };
+                if symbol.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Symbol(symbol));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn statement(mut self) -> Result<Statement, String> {
+        let statement = if self.keyword("CREATE") {
+            self.expect_keyword("DATABASE")?;
+            Statement::CreateDatabase(self.identifier()?)
+        } else if self.keyword("DROP") {
+            if self.keyword("DATABASE") {
+                Statement::DropDatabase(self.identifier()?)
+            } else if self.keyword("MEASUREMENT") {
+                Statement::DropMeasurement(self.identifier()?)
+            } else if self.keyword("SHARD") {
+                // Shard ids are unsigned, so can be too large for `number`
+                match self.next() {
+                    Some(&Token::Number(ref id)) if id.parse::<u64>().is_ok() => Statement::DropShard,
+                    _ => return Err(self.unsupported()),
+                }
+            } else if self.keyword("SERIES") {
+                self.delete()?
+            } else {
+                return Err(self.unsupported());
+            }
+        } else if self.keyword("DELETE") {
+            self.delete()?
+        } else if self.keyword("SHOW") {
+            if self.keyword("DATABASES") {
+                Statement::ShowDatabases
+            } else if self.keyword("MEASUREMENTS") {
+                Statement::ShowMeasurements
+            } else {
+                return Err(self.unsupported());
+            }
+        } else if self.keyword("SELECT") {
+            self.select()?
+        } else {
+            return Err(self.unsupported());
+        };
+
+        if self.position == self.tokens.len() {
+            Ok(statement)
+        } else {
+            Err(self.unsupported())
+        }
+    }
+
+    fn delete(&mut self) -> Result<Statement, String> {
+        let measurement = if self.keyword("FROM") { Some(self.identifier()?) } else { None };
+        let conditions = self.conditions()?;
+        Ok(Statement::Delete(measurement, conditions))
+    }
+
+    fn select(&mut self) -> Result<Statement, String> {
+        let columns = if self.symbol("*") {
+            None
+        } else {
+            let mut columns = vec![self.identifier()?];
+            while self.symbol(",") {
+                columns.push(self.identifier()?);
+            }
+            Some(columns)
+        };
+        self.expect_keyword("FROM")?;
+        let measurement = self.identifier()?;
+        let conditions = self.conditions()?;
+        Ok(Statement::Select(columns, measurement, conditions))
+    }
+
+    fn conditions(&mut self) -> Result<Vec<Condition>, String> {
+        let mut conditions = Vec::new();
+        if !self.keyword("WHERE") {
+            return Ok(conditions);
+        }
+
+        loop {
+            let key = self.identifier()?;
+            if key.eq_ignore_ascii_case("time") {
+                let condition = match self.next() {
+                    Some(&Token::Symbol(">=")) => Condition::TimeAtLeast,
+                    Some(&Token::Symbol(">")) => Condition::TimeAfter,
+                    Some(&Token::Symbol("<")) => Condition::TimeBefore,
+                    Some(&Token::Symbol("<=")) => Condition::TimeAtMost,
+                    _ => return Err(self.unsupported()),
+                };
+                conditions.push(condition(self.number()?));
+            } else {
+                if !self.symbol("=") {
+                    return Err(self.unsupported());
+                }
+                match self.next() {
+                    Some(&Token::Str(ref value)) => conditions.push(Condition::TagEquals(key, value.clone())),
+                    _ => return Err(self.unsupported()),
+                }
+            }
+
+            if !self.keyword("AND") {
+                return Ok(conditions);
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(&Token::Word(ref word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unsupported())
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(&Token::Symbol(s)) if s == symbol => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(&Token::Word(ref name)) | Some(&Token::Quoted(ref name)) => Ok(name.clone()),
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some(&Token::Number(ref number)) => number.parse().map_err(|_| self.unsupported()),
+            _ => Err(self.unsupported()),
+        }
+    }
+
+    fn unsupported(&self) -> String {
+        String::from("the mock server does not support this statement")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Condition, Statement};
+
+    #[test]
+    fn statements_are_separated() {
+        let statements = parse(r#"CREATE DATABASE "db"; SHOW measurements;"#).unwrap();
+
+        assert_eq!(statements, vec![Ok(Statement::CreateDatabase(String::from("db"))),
+                                    Ok(Statement::ShowMeasurements)]);
+    }
+
+    #[test]
+    fn selects_are_parsed() {
+        let statements = parse(r#"SELECT "value",host FROM "cpu" WHERE "region"='us-west' AND time >= 5"#).unwrap();
+
+        assert_eq!(statements, vec![Ok(Statement::Select(Some(vec![String::from("value"), String::from("host")]),
+                                                         String::from("cpu"),
+                                                         vec![Condition::TagEquals(String::from("region"),
+                                                                                   String::from("us-west")),
+                                                              Condition::TimeAtLeast(5)]))]);
+    }
+
+    #[test]
+    fn escaped_strings_stay_strings() {
+        let statements = parse(r#"DELETE WHERE "customer" = 'x\' OR \'a\' = \'a'"#).unwrap();
+
+        assert_eq!(statements, vec![Ok(Statement::Delete(None,
+                                                         vec![Condition::TagEquals(String::from("customer"),
+                                                                                   String::from("x' OR 'a' = 'a"))]))]);
+    }
+
+    #[test]
+    fn other_statements_are_unsupported() {
+        let statements = parse("SELECT mean(value) FROM cpu; SHOW USERS").unwrap();
+
+        assert!(statements.iter().all(|statement| statement.is_err()));
+    }
+}
@@ -0,0 +1,590 @@
+//! A stand-in for InfluxDB 1.x that runs in the test process, for tests
+//! that can't rely on a real server. Requires the `mock` feature.
+//!
+//! To check what a client sends without any network at all, give it a
+//! `Recorder` instead; see `AsyncDb::recording`.
+//!
+//! It accepts writes over HTTP and UDP, keeps the points in memory, and
+//! answers the InfluxQL that this crate sends: creating and dropping
+//! databases, `DELETE`, `DROP SERIES`, `DROP MEASUREMENT`, `SHOW
+//! DATABASES`, `SHOW MEASUREMENTS`, and `SELECT` of fields and tags
+//! filtered by tag equality and time. Anything else is answered with an
+//! error.
+//!
+//! ```
+//! extern crate influxdb;
+//! extern crate tokio_core;
+//!
+//! use influxdb::AsyncDb;
+//! use influxdb::mock::MockServer;
+//!
+//! fn main() {
+//!     let server = MockServer::start().expect("Unable to start the mock server");
+//!     server.create_database("my_database");
+//!
+//!     let mut core = tokio_core::reactor::Core::new()
+//!         .expect("Unable to create reactor core");
+//!     let async_db = AsyncDb::new(core.handle(), &server.base_url(), "my_database")
+//!         .expect("Unable to create AsyncDb");
+//!     core.run(async_db.add_data("cpu,host=server01 value=0.64"))
+//!         .expect("Unable to run future to completion");
+//!
+//!     assert_eq!(server.points("my_database")[0].tags["host"], "server01");
+//! }
+//! ```
+
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{self, Map, Value as Json};
+use url::form_urlencoded;
+
+use {rfc3339, Precision};
+
+mod influxql;
+mod points;
+mod recording;
+
+pub use self::points::{Point, Value};
+pub use self::recording::{RecordedDatagram, RecordedRequest, Recorder, RecordingConnector, RecordingSocket,
+                          RecordingStream};
+
+use self::influxql::{Condition, Statement};
+
+/// The version the mock server claims to be in `/ping`
+pub const VERSION: &'static str = "1.8.10";
+
+/// A running mock server, which stops when dropped
+pub struct MockServer {
+    state: Arc<Mutex<State>>,
+    http_addr: SocketAddr,
+    udp_addr: SocketAddr,
+    stopping: Arc<AtomicBool>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct State {
+    databases: BTreeMap<String, Vec<Point>>,
+    udp_database: String,
+    latency: Duration,
+    errors: VecDeque<(u16, String)>,
+}
+
+impl MockServer {
+    /// Listens for HTTP and UDP on ports chosen by the OS
+    pub fn start() -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(State {
+            udp_database: String::from("udp"),
+            ..State::default()
+        }));
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let http_addr = listener.local_addr()?;
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        let udp_addr = socket.local_addr()?;
+        // Wake up now and then to notice when the server is dropped
+        socket.set_read_timeout(Some(Duration::from_millis(50)))?;
+
+        let http = {
+            let state = state.clone();
+            let stopping = stopping.clone();
+            thread::spawn(move || serve_http(&listener, &state, &stopping))
+        };
+        let udp = {
+            let state = state.clone();
+            let stopping = stopping.clone();
+            thread::spawn(move || serve_udp(&socket, &state, &stopping))
+        };
+
+        Ok(MockServer {
+            state: state,
+            http_addr: http_addr,
+            udp_addr: udp_addr,
+            stopping: stopping,
+            threads: vec![http, udp],
+        })
+    }
+
+    /// The URL to give to `AsyncDb::new`
+    pub fn base_url(&self) -> String {
+        format!("http://{}/", self.http_addr)
+    }
+
+    /// The address to give to `AsyncUdpDb::new`
+    pub fn udp_addr(&self) -> SocketAddr {
+        self.udp_addr
+    }
+
+    /// Stores points sent over UDP in `database`, which is created when
+    /// needed. The default is `udp`.
+    pub fn set_udp_database(&self, database: &str) {
+        self.state().udp_database = database.to_owned();
+    }
+
+    pub fn create_database(&self, database: &str) {
+        self.state().databases.entry(database.to_owned()).or_insert_with(Vec::new);
+    }
+
+    pub fn databases(&self) -> Vec<String> {
+        self.state().databases.keys().cloned().collect()
+    }
+
+    /// Every point stored in `database`, in the order they were written
+    pub fn points(&self, database: &str) -> Vec<Point> {
+        self.state().databases.get(database).cloned().unwrap_or_default()
+    }
+
+    /// Answers the next HTTP request, whatever it is, with `status` and
+    /// an InfluxDB style `{"error": message}`. Failures queue up, so
+    /// calling this twice fails the next two requests.
+    pub fn fail_next(&self, status: u16, message: &str) {
+        self.state().errors.push_back((status, message.to_owned()));
+    }
+
+    /// Waits this long before answering each HTTP request
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    fn state(&self) -> MutexGuard<State> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        // Wake the HTTP thread from `accept`
+        let _ = TcpStream::connect(self.http_addr);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A panicking test shouldn't stop other tests from using the server
+fn lock(state: &Mutex<State>) -> MutexGuard<State> {
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn now() -> i64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_secs() as i64 * 1_000_000_000 + i64::from(since_epoch.subsec_nanos())
+}
+
+fn serve_udp(socket: &UdpSocket, state: &Arc<Mutex<State>>, stopping: &AtomicBool) {
+    let mut buffer = vec![0; 65536];
+    while !stopping.load(Ordering::SeqCst) {
+        let len = match socket.recv_from(&mut buffer) {
+            Ok((len, _)) => len,
+            Err(_) => continue,
+        };
+
+        // Like InfluxDB, drop whatever can't be parsed
+        let data = String::from_utf8_lossy(&buffer[..len]);
+        if let Ok(points) = points::parse(&data, 1, now()) {
+            let mut state = lock(state);
+            let database = state.udp_database.clone();
+            state.databases.entry(database).or_insert_with(Vec::new).extend(points);
+        }
+    }
+}
+
+fn serve_http(listener: &TcpListener, state: &Arc<Mutex<State>>, stopping: &Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(stream) = stream {
+            let state = state.clone();
+            thread::spawn(move || {
+                let _ = answer(stream, &state);
+            });
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    params: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: Json) -> Self {
+        Response {
+            status: status,
+            content_type: "application/json",
+            body: serde_json::to_string(&body).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Response::json(status, json!({ "error": message }))
+    }
+
+    fn no_content() -> Self {
+        Response {
+            status: 204,
+            content_type: "application/json",
+            body: String::new(),
+        }
+    }
+}
+
+fn answer(mut stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let request = read_request(&mut stream)?;
+
+    let (latency, error) = {
+        let mut state = lock(state);
+        (state.latency, state.errors.pop_front())
+    };
+    thread::sleep(latency);
+
+    let response = match error {
+        Some((status, message)) => Response::error(status, &message),
+        None => route(&request, state),
+    };
+    write_response(&mut stream, &response)
+}
+
+fn write_response<W>(stream: &mut W, response: &Response) -> io::Result<()>
+    where W: Write
+{
+    write!(stream,
+           "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+            X-Influxdb-Build: OSS\r\nX-Influxdb-Version: {}\r\nConnection: close\r\n\r\n{}",
+           response.status, reason(response.status), response.content_type, response.body.len(), VERSION,
+           response.body)
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn route(request: &Request, state: &Mutex<State>) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/ping") | ("HEAD", "/ping") => Response::no_content(),
+        ("POST", "/write") => write_points(request, state),
+        ("GET", "/query") | ("POST", "/query") => query(request, state),
+        (_, "/ping") | (_, "/write") | (_, "/query") => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn write_points(request: &Request, state: &Mutex<State>) -> Response {
+    let database = match request.param("db") {
+        Some(database) => database,
+        None => return Response::error(400, "database is required"),
+    };
+    let precision = match precision(request.param("precision").unwrap_or("ns")) {
+        Some(precision) => precision,
+        None => return Response::error(400, "invalid precision"),
+    };
+    let body = match String::from_utf8(request.body.clone()) {
+        Ok(body) => body,
+        Err(_) => return Response::error(400, "the body is not UTF-8"),
+    };
+    let points = match points::parse(&body, precision.nanoseconds() as i64, now()) {
+        Ok(points) => points,
+        Err(e) => return Response::error(400, &e),
+    };
+
+    match lock(state).databases.get_mut(database) {
+        Some(stored) => {
+            stored.extend(points);
+            Response::no_content()
+        }
+        None => Response::error(404, &format!("database not found: \"{}\"", database)),
+    }
+}
+
+fn precision(name: &str) -> Option<Precision> {
+    Some(match name {
+        "n" | "ns" => Precision::Nanoseconds,
+        "u" | "us" | "µ" => Precision::Microseconds,
+        "ms" => Precision::Milliseconds,
+        "s" => Precision::Seconds,
+        "m" => Precision::Minutes,
+        "h" => Precision::Hours,
+        _ => return None,
+    })
+}
+
+fn query(request: &Request, state: &Mutex<State>) -> Response {
+    let statements = match influxql::parse(request.param("q").unwrap_or("")) {
+        Ok(statements) => statements,
+        Err(e) => return Response::error(400, &format!("error parsing query: {}", e)),
+    };
+    if request.method == "GET" {
+        let post_only = statements.iter()
+            .filter_map(|statement| statement.as_ref().ok().and_then(Statement::post_only))
+            .next();
+        if let Some(keywords) = post_only {
+            return Response::error(405, &format!("{} requires POST", keywords));
+        }
+    }
+    let epoch = match request.param("epoch") {
+        Some(epoch) => {
+            match precision(epoch) {
+                Some(precision) => Some(precision),
+                None => return Response::error(400, "invalid epoch"),
+            }
+        }
+        None => None,
+    };
+    let database = request.param("db").unwrap_or("");
+
+    let mut results = Vec::new();
+    let mut state = lock(state);
+    for (i, statement) in statements.iter().enumerate() {
+        let result = statement.clone().and_then(|statement| execute(&mut state, database, statement, epoch));
+        match result {
+            Ok(series) => {
+                let mut result = Map::new();
+                result.insert(String::from("statement_id"), Json::from(i));
+                if !series.is_empty() {
+                    result.insert(String::from("series"), Json::Array(series));
+                }
+                results.push(Json::Object(result));
+            }
+            Err(e) => {
+                // InfluxDB 1.x stops at the first error, and when there
+                // are statements after it, reports that one as not
+                // executed rather than with its error
+                let error = if i + 1 < statements.len() { String::from("not executed") } else { e };
+                results.push(json!({ "statement_id": i, "error": error }));
+                break;
+            }
+        }
+    }
+
+    Response::json(200, json!({ "results": results }))
+}
+
+fn execute(state: &mut State, database: &str, statement: Statement, epoch: Option<Precision>) -> Result<Vec<Json>, String> {
+    if let Statement::CreateDatabase(name) = statement {
+        state.databases.entry(name).or_insert_with(Vec::new);
+        return Ok(vec![]);
+    }
+    if let Statement::DropDatabase(name) = statement {
+        state.databases.remove(&name);
+        return Ok(vec![]);
+    }
+    if let Statement::ShowDatabases = statement {
+        let values: Vec<_> = state.databases.keys().map(|name| json!([name])).collect();
+        return Ok(vec![json!({ "name": "databases", "columns": ["name"], "values": values })]);
+    }
+
+    let points = state.databases.get_mut(database)
+        .ok_or_else(|| format!("database not found: {}", database))?;
+
+    match statement {
+        Statement::DropMeasurement(measurement) => {
+            points.retain(|point| point.measurement != measurement);
+            Ok(vec![])
+        }
+        Statement::DropShard => Ok(vec![]),
+        Statement::Delete(measurement, conditions) => {
+            points.retain(|point| {
+                let measurement_matches = measurement.as_ref().map_or(true, |m| *m == point.measurement);
+                !(measurement_matches && conditions.iter().all(|c| c.matches(&point.tags, point.timestamp)))
+            });
+            Ok(vec![])
+        }
+        Statement::ShowMeasurements => {
+            let measurements: BTreeSet<_> = points.iter().map(|point| point.measurement.as_str()).collect();
+            if measurements.is_empty() {
+                return Ok(vec![]);
+            }
+            let values: Vec<_> = measurements.iter().map(|name| json!([name])).collect();
+            Ok(vec![json!({ "name": "measurements", "columns": ["name"], "values": values })])
+        }
+        Statement::Select(columns, measurement, conditions) => Ok(select(points, columns, &measurement, &conditions, epoch)),
+        Statement::CreateDatabase(..) | Statement::DropDatabase(..) | Statement::ShowDatabases => unreachable!(),
+    }
+}
+
+fn select(points: &[Point],
+          columns: Option<Vec<String>>,
+          measurement: &str,
+          conditions: &[Condition],
+          epoch: Option<Precision>)
+          -> Vec<Json>
+{
+    let mut matching: Vec<_> = points.iter()
+        .filter(|point| point.measurement == measurement)
+        .filter(|point| conditions.iter().all(|c| c.matches(&point.tags, point.timestamp)))
+        .collect();
+    matching.sort_by_key(|point| point.timestamp);
+
+    // `*` is every field and tag, sorted by name
+    let columns = columns.unwrap_or_else(|| {
+        let names: BTreeSet<_> = matching.iter()
+            .flat_map(|point| point.fields.keys().chain(point.tags.keys()))
+            .cloned()
+            .collect();
+        names.into_iter().collect()
+    });
+
+    // Like InfluxDB, only rows with at least one of the fields
+    let values: Vec<_> = matching.iter()
+        .filter(|point| columns.iter().any(|column| point.fields.contains_key(column)))
+        .map(|point| {
+            let time = match epoch {
+                Some(precision) => Json::from(point.timestamp / precision.nanoseconds() as i64),
+                None => {
+                    let time = Precision::Nanoseconds.system_time(point.timestamp).unwrap_or(UNIX_EPOCH);
+                    Json::from(rfc3339::format(time))
+                }
+            };
+            let mut row = vec![time];
+            row.extend(columns.iter().map(|column| {
+                match (point.fields.get(column), point.tags.get(column)) {
+                    (Some(field), _) => field.to_json(),
+                    (None, Some(tag)) => Json::from(tag.clone()),
+                    (None, None) => Json::Null,
+                }
+            }));
+            Json::Array(row)
+        })
+        .collect();
+
+    if values.is_empty() {
+        return vec![];
+    }
+
+    let mut names = vec![String::from("time")];
+    names.extend(columns);
+    vec![json!({ "name": measurement, "columns": names, "values": values })]
+}
+
+/// Reads one request, failing with `UnexpectedEof` if `stream` ends
+/// before all of it has arrived
+fn read_request<R>(stream: &mut R) -> io::Result<Request>
+    where R: Read
+{
+    let mut data = Vec::new();
+    let mut buffer = [0; 4096];
+    let header_end = loop {
+        if let Some(i) = find(&data, b"\r\n\r\n") {
+            break i + 4;
+        }
+        let len = stream.read(&mut buffer)?;
+        if len == 0 {
+            return Err(ended_early());
+        }
+        data.extend_from_slice(&buffer[..len]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_owned();
+    let target = request_line.next().unwrap_or("/").to_owned();
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.trim().to_lowercase(), value.trim().to_owned())),
+                _ => None,
+            }
+        })
+        .collect();
+    let header = |name: &str| headers.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| value.clone());
+
+    let mut body = data[header_end..].to_vec();
+    if header("transfer-encoding").map_or(false, |value| value.eq_ignore_ascii_case("chunked")) {
+        while !(body == b"0\r\n\r\n" || body.ends_with(b"\r\n0\r\n\r\n")) {
+            let len = stream.read(&mut buffer)?;
+            if len == 0 {
+                return Err(ended_early());
+            }
+            body.extend_from_slice(&buffer[..len]);
+        }
+        body = dechunk(&body);
+    } else {
+        let length = header("content-length").and_then(|value| value.parse().ok()).unwrap_or(0);
+        while body.len() < length {
+            let len = stream.read(&mut buffer)?;
+            if len == 0 {
+                return Err(ended_early());
+            }
+            body.extend_from_slice(&buffer[..len]);
+        }
+        body.truncate(length);
+    }
+
+    let (path, query) = match target.find('?') {
+        Some(i) => (target[..i].to_owned(), target[i + 1..].to_owned()),
+        None => (target.clone(), String::new()),
+    };
+    let mut params: Vec<_> = form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+    if header("content-type").map_or(false, |value| value.starts_with("application/x-www-form-urlencoded")) {
+        params.extend(form_urlencoded::parse(&body).into_owned());
+    }
+
+    Ok(Request {
+        method: method,
+        path: path,
+        params: params,
+        body: body,
+    })
+}
+
+fn ended_early() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "the request ended early")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn dechunk(mut chunked: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    while let Some(line_end) = find(chunked, b"\r\n") {
+        let size = String::from_utf8_lossy(&chunked[..line_end]);
+        let size = match usize::from_str_radix(size.trim(), 16) {
+            Ok(size) if size > 0 => size,
+            _ => break,
+        };
+        let start = line_end + 2;
+        let end = cmp::min(start + size, chunked.len());
+        body.extend_from_slice(&chunked[start..end]);
+        chunked = &chunked[cmp::min(end + 2, chunked.len())..];
+    }
+    body
+}
@@ -0,0 +1,212 @@
+//! Parsing the line protocol back into points
+
+use std::collections::BTreeMap;
+
+use serde_json;
+
+/// A point as the mock server stores it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub measurement: String,
+    pub tags: BTreeMap<String, String>,
+    pub fields: BTreeMap<String, Value>,
+    /// Nanoseconds since the UNIX epoch
+    pub timestamp: i64,
+}
+
+/// The value of a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Float(f64),
+    Integer(i64),
+    UnsignedInteger(u64),
+    String(String),
+    Boolean(bool),
+}
+
+impl Value {
+    pub fn to_json(&self) -> serde_json::Value {
+        match *self {
+            Value::Float(f) => serde_json::Value::from(f),
+            Value::Integer(i) => serde_json::Value::from(i),
+            Value::UnsignedInteger(u) => serde_json::Value::from(u),
+            Value::String(ref s) => serde_json::Value::from(s.clone()),
+            Value::Boolean(b) => serde_json::Value::from(b),
+        }
+    }
+}
+
+/// Parses every point in `lines`. Timestamps are in units of
+/// `precision` nanoseconds; points without one are given `now`.
+pub fn parse(lines: &str, precision: i64, now: i64) -> Result<Vec<Point>, String> {
+    lines.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_line(line, precision, now).map_err(|e| format!("unable to parse '{}': {}", line, e)))
+        .collect()
+}
+
+fn parse_line(line: &str, precision: i64, now: i64) -> Result<Point, String> {
+    let sections = split(line, ' ');
+    let (series, fields, timestamp) = match sections.len() {
+        2 => (sections[0], sections[1], None),
+        3 => (sections[0], sections[1], Some(sections[2])),
+        _ => return Err(String::from("expected a series, fields and an optional timestamp")),
+    };
+
+    let mut series = split(series, ',').into_iter();
+    let measurement = unescape(series.next().unwrap_or(""));
+    if measurement.is_empty() {
+        return Err(String::from("missing measurement"));
+    }
+
+    let mut tags = BTreeMap::new();
+    for tag in series {
+        let (key, value) = key_value(tag)?;
+        tags.insert(unescape(key), unescape(value));
+    }
+
+    let mut parsed_fields = BTreeMap::new();
+    for field in split(fields, ',') {
+        let (key, value) = key_value(field)?;
+        parsed_fields.insert(unescape(key), field_value(value)?);
+    }
+
+    let timestamp = match timestamp {
+        Some(timestamp) => {
+            timestamp.parse::<i64>().ok()
+                .and_then(|timestamp| timestamp.checked_mul(precision))
+                .ok_or_else(|| format!("invalid timestamp {}", timestamp))?
+        }
+        None => now,
+    };
+
+    Ok(Point {
+        measurement: measurement,
+        tags: tags,
+        fields: parsed_fields,
+        timestamp: timestamp,
+    })
+}
+
+/// Splits on `separator` where it isn't escaped or inside a string
+fn split(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    let mut in_string = false;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' && separator != '=' {
+            in_string = !in_string;
+        } else if c == separator && !in_string {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn key_value(pair: &str) -> Result<(&str, &str), String> {
+    let parts = split(pair, '=');
+    if parts.len() < 2 || parts[0].is_empty() {
+        return Err(format!("expected key=value but found '{}'", pair));
+    }
+    // Only the key is split; an `=` in a string value is kept
+    let key = parts[0];
+    Ok((key, &pair[key.len() + 1..]))
+}
+
+fn field_value(value: &str) -> Result<Value, String> {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return Ok(Value::String(unescape(&value[1..value.len() - 1])));
+    }
+
+    let invalid = || format!("invalid field value {}", value);
+    if value.ends_with('i') {
+        return value[..value.len() - 1].parse().map(Value::Integer).map_err(|_| invalid());
+    }
+    if value.ends_with('u') {
+        return value[..value.len() - 1].parse().map(Value::UnsignedInteger).map_err(|_| invalid());
+    }
+    match value {
+        "t" | "T" | "true" | "True" | "TRUE" => return Ok(Value::Boolean(true)),
+        "f" | "F" | "false" | "False" | "FALSE" => return Ok(Value::Boolean(false)),
+        _ => {}
+    }
+    match value.parse::<f64>() {
+        Ok(f) if f.is_finite() => Ok(Value::Float(f)),
+        _ => Err(invalid()),
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.peek() {
+                Some(&next) if ",= \"\\".contains(next) => {
+                    unescaped.push(next);
+                    chars.next();
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        unescaped.push(c);
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Value};
+
+    #[test]
+    fn points_are_parsed() {
+        let points = parse("\ncpu,host=server01,region=us-west value=0.64,count=3i,ok=t,note=\"a, b\" 1434055562\n",
+                           1_000_000_000, 7)
+            .unwrap();
+
+        assert_eq!(points.len(), 1);
+        let point = &points[0];
+        assert_eq!(point.measurement, "cpu");
+        assert_eq!(point.tags["host"], "server01");
+        assert_eq!(point.tags["region"], "us-west");
+        assert_eq!(point.fields["value"], Value::Float(0.64));
+        assert_eq!(point.fields["count"], Value::Integer(3));
+        assert_eq!(point.fields["ok"], Value::Boolean(true));
+        assert_eq!(point.fields["note"], Value::String(String::from("a, b")));
+        assert_eq!(point.timestamp, 1434055562000000000);
+    }
+
+    #[test]
+    fn points_without_a_timestamp_are_given_now() {
+        let points = parse("cpu value=1u", 1, 7).unwrap();
+        assert_eq!(points[0].timestamp, 7);
+        assert_eq!(points[0].fields["value"], Value::UnsignedInteger(1));
+    }
+
+    #[test]
+    fn escapes_are_removed() {
+        let points = parse(r#"my\ cpu,the\,host=a\=b value="say \"hi\" \\o/""#, 1, 0).unwrap();
+
+        assert_eq!(points[0].measurement, "my cpu");
+        assert_eq!(points[0].tags["the,host"], "a=b");
+        assert_eq!(points[0].fields["value"], Value::String(String::from(r#"say "hi" \o/"#)));
+    }
+
+    #[test]
+    fn invalid_points_are_rejected() {
+        assert!(parse("cpu", 1, 0).is_err());
+        assert!(parse("cpu value=", 1, 0).is_err());
+        assert!(parse("cpu value=1 not_a_time", 1, 0).is_err());
+        assert!(parse(",host=a value=1", 1, 0).is_err());
+    }
+}
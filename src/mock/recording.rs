@@ -0,0 +1,305 @@
+//! Transports for `AsyncDb` and `AsyncUdpDb` that never touch the
+//! network, but record everything sent through them.
+//!
+//! Writes are accepted and their points parsed, so tests can check
+//! exactly what was written. Queries and every other request are
+//! answered from a script of responses.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+use futures::{self, Async, Poll};
+use futures::task::{self, Task};
+use hyper::{self, Uri};
+use hyper::client::Service;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use {AsyncDb, AsyncUdpDb, DatagramSocket, Error, Result};
+
+use super::{now, points, precision, read_request, write_response, Point, Request, Response};
+
+/// Only used for the `Host` header; nothing is connected to
+const BASE_URL: &'static str = "http://localhost:8086/";
+
+/// Records what is sent through its transports, and holds the
+/// responses to give. Clones share the same recording.
+///
+/// ```
+/// extern crate influxdb;
+/// extern crate tokio_core;
+///
+/// use influxdb::AsyncDb;
+/// use influxdb::mock::Recorder;
+///
+/// fn main() {
+///     let recorder = Recorder::new();
+///     recorder.respond(200, r#"{"results":[{"statement_id":0}]}"#);
+///
+///     let mut core = tokio_core::reactor::Core::new()
+///         .expect("Unable to create reactor core");
+///     let async_db = AsyncDb::recording(core.handle(), &recorder, "my_database")
+///         .expect("Unable to create AsyncDb");
+///     core.run(async_db.add_data("cpu,host=server01 value=0.64"))
+///         .expect("Unable to run future to completion");
+///
+///     let requests = recorder.requests();
+///     assert_eq!(requests[0].endpoint, "/write");
+///     assert_eq!(requests[0].param("db"), Some("my_database"));
+///     assert_eq!(recorder.points()[0].tags["host"], "server01");
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Recorder(Rc<RefCell<Recording>>);
+
+#[derive(Default)]
+struct Recording {
+    requests: Vec<RecordedRequest>,
+    datagrams: Vec<RecordedDatagram>,
+    /// Every point from both, in the order they were sent
+    points: Vec<Point>,
+    responses: VecDeque<Response>,
+}
+
+/// An HTTP request, as `Recorder` received it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// The path, such as `/write` or `/query`
+    pub endpoint: String,
+    /// The parameters from the query string and any form body
+    pub params: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// The points in the body of a write, which is empty for other
+    /// requests
+    pub points: Vec<Point>,
+}
+
+impl RecordedRequest {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| value.as_str())
+    }
+}
+
+/// A datagram, as `Recorder` received it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedDatagram {
+    pub to: SocketAddr,
+    pub body: Vec<u8>,
+    pub points: Vec<Point>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Every HTTP request, in the order they were sent
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.0.borrow().requests.clone()
+    }
+
+    /// Every datagram, in the order they were sent
+    pub fn datagrams(&self) -> Vec<RecordedDatagram> {
+        self.0.borrow().datagrams.clone()
+    }
+
+    /// Every point written over HTTP or UDP, in the order they were sent
+    pub fn points(&self) -> Vec<Point> {
+        self.0.borrow().points.clone()
+    }
+
+    /// Answers the next request that isn't a write with `status` and
+    /// `body`, which is usually JSON but is CSV for Flux and SQL.
+    /// Responses queue up; once they run out, requests are answered
+    /// with a single empty result.
+    pub fn respond(&self, status: u16, body: &str) {
+        let content_type = if body.trim_start().starts_with('{') { "application/json" } else { "text/csv" };
+        self.0.borrow_mut().responses.push_back(Response {
+            status: status,
+            content_type: content_type,
+            body: body.to_owned(),
+        });
+    }
+
+    /// Forgets what has been recorded, but not the scripted responses
+    pub fn clear(&self) {
+        let mut recording = self.0.borrow_mut();
+        recording.requests.clear();
+        recording.datagrams.clear();
+        recording.points.clear();
+    }
+
+    pub fn connector(&self) -> RecordingConnector {
+        RecordingConnector(self.clone())
+    }
+
+    pub fn socket(&self) -> RecordingSocket {
+        RecordingSocket(self.clone())
+    }
+
+    fn answer(&self, request: Request) -> Response {
+        let mut recording = self.0.borrow_mut();
+        let written = match request.path.as_str() {
+            "/write" | "/api/v2/write" => Some(parse_write(&request)),
+            _ => None,
+        };
+
+        let (points, response) = match written {
+            Some(Ok(points)) => (points, Response::no_content()),
+            Some(Err(e)) => (Vec::new(), Response::error(400, &e)),
+            None => {
+                let response = recording.responses.pop_front()
+                    .unwrap_or_else(|| Response::json(200, json!({ "results": [{ "statement_id": 0 }] })));
+                (Vec::new(), response)
+            }
+        };
+
+        recording.points.extend(points.iter().cloned());
+        recording.requests.push(RecordedRequest {
+            method: request.method,
+            endpoint: request.path,
+            params: request.params,
+            body: request.body,
+            points: points,
+        });
+        response
+    }
+}
+
+fn parse_write(request: &Request) -> ::std::result::Result<Vec<Point>, String> {
+    let precision = precision(request.param("precision").unwrap_or("ns"))
+        .ok_or_else(|| String::from("invalid precision"))?;
+    let body = String::from_utf8(request.body.clone()).map_err(|_| String::from("the body is not UTF-8"))?;
+    points::parse(&body, precision.nanoseconds() as i64, now())
+}
+
+/// Connects every HTTP request to its `Recorder`
+#[derive(Clone)]
+pub struct RecordingConnector(Recorder);
+
+impl Service for RecordingConnector {
+    type Request = Uri;
+    type Response = RecordingStream;
+    type Error = io::Error;
+    type Future = futures::future::FutureResult<RecordingStream, io::Error>;
+
+    fn call(&self, _uri: Uri) -> Self::Future {
+        futures::future::ok(RecordingStream {
+            recorder: self.0.clone(),
+            written: Vec::new(),
+            response: None,
+            reader: None,
+        })
+    }
+}
+
+/// One connection made by `RecordingConnector`. The response can be
+/// read as soon as the whole request has been written.
+pub struct RecordingStream {
+    recorder: Recorder,
+    written: Vec<u8>,
+    response: Option<Cursor<Vec<u8>>>,
+    /// The task that tried to read before there was a response
+    reader: Option<Task>,
+}
+
+impl Read for RecordingStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.response {
+            Some(ref mut response) => response.read(buf),
+            None => {
+                // hyper may look for the response before it has written
+                // all of the request; `write` wakes it once there is one
+                self.reader = Some(task::current());
+                Err(io::Error::new(io::ErrorKind::WouldBlock, "the request has not been written yet"))
+            }
+        }
+    }
+}
+
+impl Write for RecordingStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.response.is_none() {
+            self.written.extend_from_slice(buf);
+            match read_request(&mut &self.written[..]) {
+                Ok(request) => {
+                    let mut response = Vec::new();
+                    write_response(&mut response, &self.recorder.answer(request))?;
+                    self.response = Some(Cursor::new(response));
+                    if let Some(reader) = self.reader.take() {
+                        reader.notify();
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for RecordingStream {}
+
+impl AsyncWrite for RecordingStream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// Records every datagram sent through it to its `Recorder`
+pub struct RecordingSocket(Recorder);
+
+impl DatagramSocket for RecordingSocket {
+    type Addr = SocketAddr;
+
+    fn send_datagram(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        // Like InfluxDB, ignore whatever can't be parsed
+        let points = points::parse(&String::from_utf8_lossy(buf), 1, now()).unwrap_or_default();
+        let mut recording = (self.0).0.borrow_mut();
+        recording.points.extend(points.iter().cloned());
+        recording.datagrams.push(RecordedDatagram {
+            to: *addr,
+            body: buf.to_vec(),
+            points: points,
+        });
+        Ok(buf.len())
+    }
+
+    fn error(error: io::Error) -> Error {
+        Error::Udp(error)
+    }
+}
+
+impl AsyncDb<RecordingConnector> {
+    /// Sends every request to `recorder` instead of InfluxDB
+    pub fn recording(handle: Handle, recorder: &Recorder, name: &str) -> Result<Self> {
+        AsyncDb::with_client(recording_client(&handle, recorder), BASE_URL, name)
+    }
+
+    /// Like `AsyncDb::v2`, but sends every request to `recorder`
+    pub fn recording_v2(handle: Handle, recorder: &Recorder, org: &str, bucket: &str, token: &str) -> Result<Self> {
+        AsyncDb::with_client_v2(recording_client(&handle, recorder), BASE_URL, org, bucket, token)
+    }
+}
+
+fn recording_client(handle: &Handle, recorder: &Recorder) -> hyper::Client<RecordingConnector> {
+    hyper::Client::configure()
+        .keep_alive(false)
+        .connector(recorder.connector())
+        .build(handle)
+}
+
+impl AsyncUdpDb<RecordingSocket> {
+    /// Sends every datagram to `recorder` instead of `ip_port`
+    pub fn recording(recorder: &Recorder, ip_port: &str) -> Result<Self> {
+        AsyncUdpDb::with_socket(recorder.socket(), ip_port)
+    }
+}
@@ -0,0 +1,167 @@
+//! Just enough RFC3339 to talk to InfluxDB without a date library
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Formats a time in UTC, with nanoseconds when there are any
+pub fn format(time: SystemTime) -> String {
+    let (seconds, nanoseconds) = match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => (d.as_secs() as i64, d.subsec_nanos()),
+        Err(e) => {
+            let d = e.duration();
+            match d.subsec_nanos() {
+                0 => (-(d.as_secs() as i64), 0),
+                n => (-(d.as_secs() as i64) - 1, 1_000_000_000 - n),
+            }
+        }
+    };
+
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let second_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    let mut formatted = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                                year, month, day,
+                                second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60);
+    if nanoseconds != 0 {
+        formatted.push_str(&format!(".{:09}", nanoseconds));
+    }
+    formatted.push('Z');
+    formatted
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.fraction](Z|±HH:MM)`
+pub fn parse(value: &str) -> Option<SystemTime> {
+    let bytes = value.as_bytes();
+    // Checked first so that slicing the fields below can't split a
+    // multi-byte character
+    if bytes.len() < 20 || !bytes[..19].is_ascii() || bytes[4] != b'-' || bytes[7] != b'-' ||
+       !(bytes[10] == b'T' || bytes[10] == b't') || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+
+    let year = number(&value[0..4])?;
+    let month = number(&value[5..7])?;
+    let day = number(&value[8..10])?;
+    let hour = number(&value[11..13])?;
+    let minute = number(&value[14..16])?;
+    let second = number(&value[17..19])?;
+    if month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = &value[19..];
+    let mut nanoseconds = 0;
+    if rest.starts_with('.') {
+        let digits = rest[1..].bytes().take_while(|b| b.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        // Only nanosecond precision is kept
+        let fraction = &rest[1..1 + digits.min(9)];
+        nanoseconds = number(fraction)? * 10i64.pow(9 - fraction.len() as u32);
+        rest = &rest[1 + digits..];
+    }
+
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            sign * (number(&rest[1..3])? * 3600 + number(&rest[4..6])? * 60)
+        }
+        _ => return None,
+    };
+
+    let seconds = days_from_civil(year, month, day) * SECONDS_PER_DAY +
+                  hour * 3600 + minute * 60 + second - offset;
+    from_unix(seconds, nanoseconds as u32)
+}
+
+fn number(digits: &str) -> Option<i64> {
+    if digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn from_unix(seconds: i64, nanoseconds: u32) -> Option<SystemTime> {
+    if seconds >= 0 {
+        UNIX_EPOCH.checked_add(Duration::new(seconds as u64, nanoseconds))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::new(seconds.wrapping_neg() as u64, 0))
+            .and_then(|time| time.checked_add(Duration::new(0, nanoseconds)))
+    }
+}
+
+// Converts between days since the UNIX epoch and proleptic Gregorian
+// dates; see http://howardhinnant.github.io/date_algorithms.html
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{format, parse};
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(format(UNIX_EPOCH + Duration::new(1434055562, 5)), "2015-06-11T20:46:02.000000005Z");
+        assert_eq!(format(UNIX_EPOCH - Duration::new(0, 500_000_000)), "1969-12-31T23:59:59.500000000Z");
+        assert_eq!(format(UNIX_EPOCH + Duration::from_secs(951782400)), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse("1970-01-01T00:00:00Z"), Some(UNIX_EPOCH));
+        assert_eq!(parse("2015-06-11T20:46:02.000000005Z"), Some(UNIX_EPOCH + Duration::new(1434055562, 5)));
+        assert_eq!(parse("2015-06-11T20:46:02.5Z"), Some(UNIX_EPOCH + Duration::new(1434055562, 500_000_000)));
+        assert_eq!(parse("2015-06-11T22:46:02+02:00"), Some(UNIX_EPOCH + Duration::from_secs(1434055562)));
+        assert_eq!(parse("1969-12-31T23:59:59.5Z"), Some(UNIX_EPOCH - Duration::new(0, 500_000_000)));
+    }
+
+    #[test]
+    fn parsing_rejects_other_formats() {
+        assert_eq!(parse("2015-06-11 20:46:02Z"), None);
+        assert_eq!(parse("2015-06-11T20:46:02"), None);
+        assert_eq!(parse("2015-13-11T20:46:02Z"), None);
+        assert_eq!(parse("2015-06-11T20:46:02.Z"), None);
+        assert_eq!(parse("1434055562"), None);
+        assert_eq!(parse("2015-06-11T20:46:0é"), None);
+        assert_eq!(parse("2015-06-11T20:46:0éZ"), None);
+        assert_eq!(parse("2015-06-11T20:46:é2Z"), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let time = UNIX_EPOCH + Duration::new(4102444799, 999_999_999);
+        assert_eq!(parse(&format(time)), Some(time));
+    }
+}
@@ -0,0 +1,460 @@
+//! SQL queries against InfluxDB 3, through `/api/v3/query_sql`.
+//!
+//! Rows arrive as JSON lines or CSV and are gathered into the same
+//! `Series` that InfluxQL queries return, so code reading results
+//! doesn't need to change when a query moves from InfluxQL to SQL.
+//!
+//! ```no_run
+//! extern crate influxdb;
+//! extern crate tokio_core;
+//!
+//! use influxdb::AsyncDb;
+//! use influxdb::sql::SqlQuery;
+//!
+//! fn main() {
+//!     let mut core = tokio_core::reactor::Core::new()
+//!         .expect("Unable to create reactor core");
+//!     let mut async_db = AsyncDb::new(core.handle(), "http://localhost:8181/", "my_database")
+//!         .expect("Unable to create AsyncDb");
+//!     async_db.set_token(Some("my_token"));
+//!
+//!     let query = SqlQuery::new("SELECT time, host, usage FROM cpu WHERE host = $host")
+//!         .param("host", "server01");
+//!     let series = core.run(async_db.sql_query(&query).series())
+//!         .expect("Unable to run future to completion");
+//!     println!("{:?}", series);
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+
+use futures::{self, Async, Future, Stream};
+use hyper::{self, Chunk};
+use hyper::client::Connect;
+use serde::de::{self, Deserialize, Deserializer, MapVisitor};
+use serde_json::{self, Value};
+
+use {check_response_code, csv, AsyncDb, Error, Result, Series};
+
+/// How the server sends rows. Either way they become `Series`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SqlFormat {
+    /// A JSON object per row, which keeps the types of values
+    JsonLines,
+    /// Values are strings, so integers, floats and booleans are
+    /// recognized by how they look
+    Csv,
+}
+
+impl SqlFormat {
+    /// The name InfluxDB uses for this format
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            SqlFormat::JsonLines => "jsonl",
+            SqlFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A SQL statement and the values of its `$name` placeholders
+#[derive(Debug, Clone)]
+pub struct SqlQuery {
+    query: String,
+    format: SqlFormat,
+    params: serde_json::Map<String, Value>,
+}
+
+impl SqlQuery {
+    /// A query whose rows are sent as JSON lines
+    pub fn new<S>(query: S) -> Self
+        where S: Into<String>
+    {
+        SqlQuery {
+            query: query.into(),
+            format: SqlFormat::JsonLines,
+            params: serde_json::Map::new(),
+        }
+    }
+
+    pub fn format(mut self, format: SqlFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Makes `value` available to the statement as `$<name>`
+    pub fn param<K, V>(mut self, name: K, value: V) -> Self
+        where K: Into<String>,
+              V: Into<Value>,
+    {
+        self.params.insert(name.into(), value.into());
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct QueryBody<'a> {
+    db: &'a str,
+    q: &'a str,
+    format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    params: Option<&'a serde_json::Map<String, Value>>,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    /// Runs a SQL query against the database, yielding rows as `Series`
+    /// as they are parsed. InfluxDB 3 needs a token; see
+    /// `AsyncDb::set_token`.
+    pub fn sql_query(&self, query: &SqlQuery) -> SqlRows {
+        let query_endpoint = match self.base_url.join("/api/v3/query_sql") {
+            Ok(url) => url,
+            Err(e) => return SqlRows(Box::new(futures::stream::once(Err(e.into())))),
+        };
+
+        let body = QueryBody {
+            db: &self.name,
+            q: &query.query,
+            format: query.format.as_str(),
+            params: if query.params.is_empty() { None } else { Some(&query.params) },
+        };
+        let body = match serde_json::to_vec(&body) {
+            Ok(body) => body,
+            Err(e) => return SqlRows(Box::new(futures::stream::once(Err(Error::Serde(e))))),
+        };
+
+        let mut request = self.request(hyper::Method::Post, &query_endpoint);
+        request.headers_mut().set(hyper::header::ContentType::json());
+        request.set_body(body);
+
+        let chunks =
+            self.client.request(request)
+            .map_err(Error::Hyper)
+            .and_then(check_response_code)
+            .map(|response| response.body().map_err(Error::Hyper))
+            .flatten_stream();
+
+        SqlRows(Box::new(ParseSeries {
+            chunks: chunks,
+            parser: SqlParser::new(query.format),
+            parsed: VecDeque::new(),
+            done: false,
+        }))
+    }
+}
+
+/// Yields each batch of rows as it is parsed. Later batches may have
+/// columns that earlier ones didn't; see `SqlRows::series` to join them.
+#[must_use = "streams do nothing unless polled"]
+pub struct SqlRows(Box<Stream<Item = Series, Error = Error>>);
+
+impl SqlRows {
+    /// Waits for every row, joining the batches into a single `Series`
+    /// with all of their columns, as an InfluxQL query would give.
+    pub fn series(self) -> SqlSeries {
+        let series = self.fold(Vec::new(), |mut all, series| {
+            for row in series.values {
+                push_row(&mut all, &series.columns, row);
+            }
+            Ok::<_, Error>(all)
+        });
+
+        SqlSeries(Box::new(series))
+    }
+}
+
+impl Stream for SqlRows {
+    type Item = Series;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        self.0.poll()
+    }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct SqlSeries(Box<Future<Item = Vec<Series>, Error = Error>>);
+
+impl Future for SqlSeries {
+    type Item = Vec<Series>;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
+/// Adds a row to the last series, or starts the first one. JSON lines
+/// leave out null values, so a row may have only some of the columns,
+/// or columns the earlier rows didn't; those are added, with nulls for
+/// the rows before.
+fn push_row(series: &mut Vec<Series>, columns: &[String], values: Vec<Value>) {
+    if let Some(last) = series.last_mut() {
+        if last.columns[..] == *columns {
+            last.values.push(values);
+            return;
+        }
+
+        let mut row = vec![Value::Null; last.columns.len()];
+        for (column, value) in columns.iter().zip(values) {
+            match last.columns.iter().position(|c| c == column) {
+                Some(position) => row[position] = value,
+                None => {
+                    last.columns.push(column.clone());
+                    for earlier in &mut last.values {
+                        earlier.push(Value::Null);
+                    }
+                    row.push(value);
+                }
+            }
+        }
+        last.values.push(row);
+        return;
+    }
+
+    series.push(Series {
+        name: String::new(),
+        columns: columns.to_vec(),
+        values: vec![values],
+        epoch: None,
+    });
+}
+
+/// Parses series from the chunks of the response body as they arrive
+struct ParseSeries<S> {
+    chunks: S,
+    parser: SqlParser,
+    parsed: VecDeque<Series>,
+    done: bool,
+}
+
+impl<S> Stream for ParseSeries<S>
+    where S: Stream<Item = Chunk, Error = Error>
+{
+    type Item = Series;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(series) = self.parsed.pop_front() {
+                return Ok(Async::Ready(Some(series)));
+            }
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+
+            let mut batch = Vec::new();
+            let parsed = match self.chunks.poll() {
+                Ok(Async::Ready(Some(chunk))) => self.parser.push(&chunk, &mut batch),
+                Ok(Async::Ready(None)) => {
+                    self.done = true;
+                    self.parser.finish(&mut batch)
+                }
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = parsed {
+                self.done = true;
+                return Err(e);
+            }
+            self.parsed.extend(batch);
+        }
+    }
+}
+
+struct SqlParser {
+    format: SqlFormat,
+    /// Bytes of a JSON line that hasn't ended yet
+    partial: Vec<u8>,
+    rows: csv::Rows,
+    header: Option<Vec<String>>,
+}
+
+impl SqlParser {
+    fn new(format: SqlFormat) -> Self {
+        SqlParser {
+            format: format,
+            partial: Vec::new(),
+            rows: csv::Rows::new(),
+            header: None,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8], series: &mut Vec<Series>) -> Result<()> {
+        let mut lines = Vec::new();
+        match self.format {
+            SqlFormat::JsonLines => {
+                for &b in bytes {
+                    if b == b'\n' {
+                        lines.push(mem::replace(&mut self.partial, Vec::new()));
+                    } else {
+                        self.partial.push(b);
+                    }
+                }
+            }
+            SqlFormat::Csv => self.rows.push(bytes, |row| lines.push(row)),
+        }
+
+        for line in lines {
+            self.line(line, series)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, series: &mut Vec<Series>) -> Result<()> {
+        let line = match self.format {
+            SqlFormat::JsonLines => mem::replace(&mut self.partial, Vec::new()),
+            SqlFormat::Csv => {
+                self.rows.finish()
+                    .ok_or_else(|| Error::SqlParse(String::from("the response ended inside a quoted value")))?
+            }
+        };
+        self.line(line, series)
+    }
+
+    fn line(&mut self, line: Vec<u8>, series: &mut Vec<Series>) -> Result<()> {
+        if line.iter().all(|b| b.is_ascii_whitespace()) {
+            return Ok(());
+        }
+
+        match self.format {
+            SqlFormat::JsonLines => {
+                let row: OrderedRow = serde_json::from_slice(&line).map_err(Error::Serde)?;
+                let (columns, values): (Vec<_>, Vec<_>) = row.0.into_iter().unzip();
+                push_row(series, &columns, values);
+            }
+            SqlFormat::Csv => {
+                let line = String::from_utf8(line).map_err(|e| Error::Utf8(e.utf8_error()))?;
+                let cells = csv::split_cells(&line);
+                match self.header {
+                    Some(ref header) => {
+                        if cells.len() != header.len() {
+                            return Err(Error::SqlParse(format!("expected {} values but found {}",
+                                                               header.len(), cells.len())));
+                        }
+                        push_row(series, header, cells.into_iter().map(csv_value).collect());
+                    }
+                    None => self.header = Some(cells),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recognizes integers, finite floats and booleans, as the JSON formats
+/// would have sent them. Empty cells are null.
+fn csv_value(cell: String) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    if let Ok(i) = cell.parse::<i64>() {
+        return Value::from(i);
+    }
+    if let Ok(u) = cell.parse::<u64>() {
+        return Value::from(u);
+    }
+    if let Some(n) = cell.parse().ok().and_then(serde_json::Number::from_f64) {
+        return Value::Number(n);
+    }
+    match cell.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(cell),
+    }
+}
+
+/// A JSON object with its keys in the order they were sent, which is
+/// the order of the columns in the query
+struct OrderedRow(Vec<(String, Value)>);
+
+impl Deserialize for OrderedRow {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+        where D: Deserializer
+    {
+        struct RowVisitor;
+
+        impl de::Visitor for RowVisitor {
+            type Value = OrderedRow;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<V>(self, mut visitor: V) -> ::std::result::Result<Self::Value, V::Error>
+                where V: MapVisitor
+            {
+                let mut row = Vec::new();
+                while let Some(entry) = visitor.visit()? {
+                    row.push(entry);
+                }
+                Ok(OrderedRow(row))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+
+    use super::{SqlFormat, SqlParser};
+    use Series;
+
+    fn parse(format: SqlFormat, chunks: &[&str]) -> Vec<Series> {
+        let mut parser = SqlParser::new(format);
+        let mut series = Vec::new();
+        for chunk in chunks {
+            parser.push(chunk.as_bytes(), &mut series).unwrap();
+        }
+        parser.finish(&mut series).unwrap();
+        series
+    }
+
+    #[test]
+    fn json_lines_keep_their_column_order() {
+        let series = parse(SqlFormat::JsonLines,
+                           &["{\"time\":\"2015-06-11T20:46:02\",\"host\":\"a\",\"usage\":0.64}\n{\"time\":",
+                             "\"2015-06-11T20:46:03\",\"host\":\"b\",\"usage\":1}\n"]);
+
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].columns, ["time", "host", "usage"]);
+        assert_eq!(series[0].values[1], [Value::from("2015-06-11T20:46:03"), Value::from("b"), Value::from(1)]);
+    }
+
+    #[test]
+    fn json_lines_without_null_values_are_filled_in() {
+        let series = parse(SqlFormat::JsonLines,
+                           &["{\"host\":\"a\",\"usage\":0.5}\n{\"host\":\"b\"}\n{\"region\":\"eu\"}"]);
+
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].columns, ["host", "usage", "region"]);
+        assert_eq!(series[0].values[0], [Value::from("a"), Value::from(0.5), Value::Null]);
+        assert_eq!(series[0].values[1], [Value::from("b"), Value::Null, Value::Null]);
+        assert_eq!(series[0].values[2], [Value::Null, Value::Null, Value::from("eu")]);
+    }
+
+    #[test]
+    fn csv_values_are_typed_by_how_they_look() {
+        let series = parse(SqlFormat::Csv,
+                           &["host,count,usage,ok,note\r\n", "a,3,0.5,true,\r\nb,-1,1e3,false,\"inf, \"\"ish\"\"\"\r\n"]);
+
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].columns, ["host", "count", "usage", "ok", "note"]);
+        assert_eq!(series[0].values[0],
+                   [Value::from("a"), Value::from(3), Value::from(0.5), Value::Bool(true), Value::Null]);
+        assert_eq!(series[0].values[1],
+                   [Value::from("b"), Value::from(-1), Value::from(1000.0), Value::Bool(false),
+                    Value::from("inf, \"ish\"")]);
+    }
+
+    #[test]
+    fn csv_rows_must_match_the_header() {
+        let mut parser = SqlParser::new(SqlFormat::Csv);
+        assert!(parser.push(b"a,b\n1\n", &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+//! Connections to InfluxDB, Telegraf, and relays listening on Unix
+//! domain sockets on the same host.
+
+use std::cell::RefCell;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use futures::{self, Future};
+use hyper::{self, Uri};
+use hyper::client::Service;
+use tokio_core::reactor::Handle;
+use tokio_uds::{UnixDatagram, UnixStream};
+
+use {AsyncDb, DatagramSocket, Error, Measurement, Result, send_datagrams};
+
+/// Makes every HTTP connection to the same socket path, whatever the
+/// host in the URL.
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    handle: Handle,
+    path: PathBuf,
+}
+
+impl UnixConnector {
+    pub fn new<P>(handle: Handle, path: P) -> Self
+        where P: Into<PathBuf>
+    {
+        UnixConnector {
+            handle: handle,
+            path: path.into(),
+        }
+    }
+}
+
+impl Service for UnixConnector {
+    type Request = Uri;
+    type Response = UnixStream;
+    type Error = io::Error;
+    type Future = futures::future::FutureResult<UnixStream, io::Error>;
+
+    fn call(&self, _uri: Uri) -> Self::Future {
+        futures::future::result(UnixStream::connect(&self.path, &self.handle))
+    }
+}
+
+impl AsyncDb<UnixConnector> {
+    /// Talks HTTP to the InfluxDB listening on the socket at `path`,
+    /// as configured by `unix-socket-enabled` and `bind-socket`.
+    pub fn unix<P>(handle: Handle, path: P, name: &str) -> Result<Self>
+        where P: Into<PathBuf>
+    {
+        let connector = UnixConnector::new(handle.clone(), path);
+        let client = hyper::Client::configure()
+            .keep_alive(false)
+            .connector(connector)
+            .build(&handle);
+        // Only used for the `Host` header; the connector ignores it
+        AsyncDb::with_client(client, "http://localhost/", name)
+    }
+}
+
+/// macOS's default limit on the size of a Unix datagram. Linux allows
+/// much larger ones.
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 2048;
+
+/// Sends data to a Unix datagram socket, such as Telegraf's
+/// `socket_listener` with a `unixgram://` address.
+///
+/// Like `AsyncUdpDb`, one socket is shared by every `add_data` call and
+/// batches are split between points to fit the maximum payload size.
+pub struct AsyncUnixDatagramDb {
+    socket: Rc<UnixDatagram>,
+    their_path: PathBuf,
+    max_payload_size: usize,
+    /// Serialization buffers handed back by completed sends
+    buffers: Rc<RefCell<Vec<Vec<u8>>>>,
+}
+
+impl AsyncUnixDatagramDb {
+    /// Creates an unbound socket that sends to `path`
+    pub fn new<P>(handle: Handle, path: P) -> Result<Self>
+        where P: Into<PathBuf>
+    {
+        let socket = UnixDatagram::unbound(&handle).map_err(Error::Unix)?;
+
+        Ok(AsyncUnixDatagramDb {
+            socket: Rc::new(socket),
+            their_path: path.into(),
+            max_payload_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            buffers: Rc::new(RefCell::new(Vec::new())),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.their_path
+    }
+
+    pub fn max_payload_size(&self) -> usize {
+        self.max_payload_size
+    }
+
+    /// Sets the largest datagram that will be sent, in bytes. Defaults
+    /// to `DEFAULT_MAX_DATAGRAM_SIZE`.
+    pub fn set_max_payload_size(&mut self, max_payload_size: usize) {
+        self.max_payload_size = max_payload_size;
+    }
+
+    pub fn add_data<T>(&self, measure: T) -> AddDataUnix
+        where T: Measurement
+    {
+        AddDataUnix(send_datagrams(&self.socket, self.their_path.clone(), self.max_payload_size, &self.buffers, measure))
+    }
+}
+
+impl DatagramSocket for UnixDatagram {
+    type Addr = PathBuf;
+
+    fn send_datagram(&self, buf: &[u8], path: &PathBuf) -> io::Result<usize> {
+        self.send_to(buf, path)
+    }
+
+    fn error(error: io::Error) -> Error {
+        Error::Unix(error)
+    }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct AddDataUnix(Box<Future<Item = (), Error = Error>>);
+
+impl Future for AddDataUnix {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
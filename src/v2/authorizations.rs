@@ -0,0 +1,227 @@
+//! The `/api/v2/authorizations` endpoints, which manage API tokens
+
+use std::fmt;
+
+use futures::{self, Future};
+use hyper;
+use hyper::client::Connect;
+use serde::{Deserialize, Deserializer};
+use serde_json;
+
+use {response_to_json, AsyncDb, Error};
+use super::ApiCall;
+
+/// An API token. `Debug` doesn't show it, so that authorizations can
+/// be logged without leaking their secrets.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token(String);
+
+impl Token {
+    /// The secret itself, to pass to `AsyncDb::v2`
+    pub fn secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Token(<redacted>)")
+    }
+}
+
+impl Deserialize for Token {
+    fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+        where D: Deserializer
+    {
+        String::deserialize(deserializer).map(Token)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "write")]
+    Write,
+}
+
+/// Inactive tokens are rejected until they are activated again
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    #[serde(rename = "active")]
+    Active,
+    #[serde(rename = "inactive")]
+    Inactive,
+}
+
+/// What a permission applies to, such as a single bucket
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resource {
+    /// Such as `buckets` or `orgs`
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    /// A single resource, or every resource of the type if `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "orgID", skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Permission {
+    pub action: Action,
+    pub resource: Resource,
+}
+
+impl Permission {
+    /// Permission to query one bucket
+    pub fn read_bucket<S, T>(org_id: S, bucket_id: T) -> Self
+        where S: Into<String>,
+              T: Into<String>,
+    {
+        Permission::bucket(Action::Read, org_id.into(), bucket_id.into())
+    }
+
+    /// Permission to write to one bucket
+    pub fn write_bucket<S, T>(org_id: S, bucket_id: T) -> Self
+        where S: Into<String>,
+              T: Into<String>,
+    {
+        Permission::bucket(Action::Write, org_id.into(), bucket_id.into())
+    }
+
+    fn bucket(action: Action, org_id: String, bucket_id: String) -> Self {
+        Permission {
+            action: action,
+            resource: Resource {
+                resource_type: String::from("buckets"),
+                id: Some(bucket_id),
+                org_id: Some(org_id),
+                name: None,
+                org: None,
+            },
+        }
+    }
+}
+
+/// A token and what it is allowed to do
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Authorization {
+    pub id: String,
+    /// The secret, which newer servers only send when the token is
+    /// created
+    #[serde(default)]
+    pub token: Option<Token>,
+    pub status: Status,
+    pub description: Option<String>,
+    #[serde(rename = "orgID")]
+    pub org_id: String,
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+/// A token to create with `AsyncDb::create_authorization`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NewAuthorization {
+    #[serde(rename = "orgID")]
+    org_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    permissions: Vec<Permission>,
+}
+
+impl NewAuthorization {
+    /// A token in the organization with the ID `org_id` that can't do
+    /// anything until it is given permissions
+    pub fn new<S>(org_id: S) -> Self
+        where S: Into<String>
+    {
+        NewAuthorization {
+            org_id: org_id.into(),
+            description: None,
+            permissions: Vec::new(),
+        }
+    }
+
+    pub fn description<S>(mut self, description: S) -> Self
+        where S: Into<String>
+    {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn permission(mut self, permission: Permission) -> Self {
+        self.permissions.push(permission);
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct Authorizations {
+    authorizations: Vec<Authorization>,
+}
+
+#[derive(Serialize)]
+struct StatusUpdate {
+    status: Status,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    /// Creates a token. Its secret is only available from the response.
+    pub fn create_authorization(&self, authorization: &NewAuthorization) -> ApiCall<Authorization> {
+        let body = match serde_json::to_vec(authorization) {
+            Ok(body) => body,
+            Err(e) => return ApiCall(Box::new(futures::future::err(Error::Serde(e)))),
+        };
+
+        let authorization =
+            self.api(hyper::Method::Post, "/api/v2/authorizations", &[], Some(body))
+            .and_then(response_to_json);
+
+        ApiCall(Box::new(authorization))
+    }
+
+    /// Lists the tokens in the organization given to `AsyncDb::v2`, or
+    /// every token that this one can read otherwise
+    pub fn authorizations(&self) -> ApiCall<Vec<Authorization>> {
+        let mut query = vec![];
+        if let Some(ref org) = self.org {
+            query.push(("org", org.as_str()));
+        }
+
+        let authorizations =
+            self.api(hyper::Method::Get, "/api/v2/authorizations", &query, None)
+            .and_then(response_to_json)
+            .map(|list: Authorizations| list.authorizations);
+
+        ApiCall(Box::new(authorizations))
+    }
+
+    /// Activates or deactivates a token
+    pub fn set_authorization_status(&self, id: &str, status: Status) -> ApiCall<Authorization> {
+        let body = match serde_json::to_vec(&StatusUpdate { status: status }) {
+            Ok(body) => body,
+            Err(e) => return ApiCall(Box::new(futures::future::err(Error::Serde(e)))),
+        };
+
+        let authorization =
+            self.api_item(hyper::Method::Patch, "/api/v2/authorizations/", id, &[], Some(body))
+            .and_then(response_to_json);
+
+        ApiCall(Box::new(authorization))
+    }
+
+    pub fn delete_authorization(&self, id: &str) -> ApiCall<()> {
+        let deleted =
+            self.api_item(hyper::Method::Delete, "/api/v2/authorizations/", id, &[], None)
+            .map(|_| ());
+
+        ApiCall(Box::new(deleted))
+    }
+}
@@ -0,0 +1,182 @@
+//! The `/api/v2/buckets` and `/api/v2/orgs` endpoints
+
+use std::time::Duration;
+
+use futures::{self, Future};
+use hyper;
+use hyper::client::Connect;
+use serde_json;
+
+use {response_to_json, AsyncDb, Error};
+use super::{ApiCall, Page};
+
+/// A bucket, as InfluxDB 2.x describes it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Bucket {
+    pub id: String,
+    #[serde(rename = "orgID")]
+    pub org_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(rename = "retentionRules", default)]
+    pub retention_rules: Vec<RetentionRule>,
+}
+
+/// How long a bucket keeps its data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionRule {
+    /// Always `expire`
+    #[serde(rename = "type")]
+    pub rule_type: String,
+    /// Points older than this are deleted; zero keeps them forever
+    #[serde(rename = "everySeconds")]
+    pub every_seconds: u64,
+    #[serde(rename = "shardGroupDurationSeconds", skip_serializing_if = "Option::is_none")]
+    pub shard_group_duration_seconds: Option<u64>,
+}
+
+impl RetentionRule {
+    /// Deletes points once they are older than `duration`
+    pub fn expire(duration: Duration) -> Self {
+        RetentionRule {
+            rule_type: String::from("expire"),
+            every_seconds: duration.as_secs(),
+            shard_group_duration_seconds: None,
+        }
+    }
+}
+
+/// An organization, as InfluxDB 2.x describes it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// A bucket to create with `AsyncDb::create_bucket`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NewBucket {
+    #[serde(rename = "orgID")]
+    org_id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(rename = "retentionRules")]
+    retention_rules: Vec<RetentionRule>,
+}
+
+impl NewBucket {
+    /// A bucket that keeps its data forever. See `AsyncDb::org_id` to
+    /// find the ID of an organization.
+    pub fn new<S, T>(org_id: S, name: T) -> Self
+        where S: Into<String>,
+              T: Into<String>,
+    {
+        NewBucket {
+            org_id: org_id.into(),
+            name: name.into(),
+            description: None,
+            retention_rules: Vec::new(),
+        }
+    }
+
+    pub fn description<S>(mut self, description: S) -> Self
+        where S: Into<String>
+    {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Deletes points once they are older than `duration`
+    pub fn retention(mut self, duration: Duration) -> Self {
+        self.retention_rules = vec![RetentionRule::expire(duration)];
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct Buckets {
+    buckets: Vec<Bucket>,
+}
+
+#[derive(Deserialize)]
+struct Organizations {
+    orgs: Vec<Organization>,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    /// Lists the buckets in the organization given to `AsyncDb::v2`,
+    /// or every bucket the token can read otherwise
+    pub fn buckets(&self, page: Page) -> ApiCall<Vec<Bucket>> {
+        let offset = page.offset.to_string();
+        let limit = page.limit.to_string();
+        let mut query = vec![("offset", offset.as_str()), ("limit", limit.as_str())];
+        if let Some(ref org) = self.org {
+            query.push(("org", org.as_str()));
+        }
+
+        let buckets =
+            self.api(hyper::Method::Get, "/api/v2/buckets", &query, None)
+            .and_then(response_to_json)
+            .map(|list: Buckets| list.buckets);
+
+        ApiCall(Box::new(buckets))
+    }
+
+    pub fn create_bucket(&self, bucket: &NewBucket) -> ApiCall<Bucket> {
+        let body = match serde_json::to_vec(bucket) {
+            Ok(body) => body,
+            Err(e) => return ApiCall(Box::new(futures::future::err(Error::Serde(e)))),
+        };
+
+        let bucket =
+            self.api(hyper::Method::Post, "/api/v2/buckets", &[], Some(body))
+            .and_then(response_to_json);
+
+        ApiCall(Box::new(bucket))
+    }
+
+    /// Deletes a bucket and all of its data
+    pub fn delete_bucket(&self, id: &str) -> ApiCall<()> {
+        let deleted =
+            self.api_item(hyper::Method::Delete, "/api/v2/buckets/", id, &[], None)
+            .map(|_| ());
+
+        ApiCall(Box::new(deleted))
+    }
+
+    /// Lists the organizations the token can read
+    pub fn orgs(&self, page: Page) -> ApiCall<Vec<Organization>> {
+        let offset = page.offset.to_string();
+        let limit = page.limit.to_string();
+        let query = [("offset", offset.as_str()), ("limit", limit.as_str())];
+
+        let orgs =
+            self.api(hyper::Method::Get, "/api/v2/orgs", &query, None)
+            .and_then(response_to_json)
+            .map(|list: Organizations| list.orgs);
+
+        ApiCall(Box::new(orgs))
+    }
+
+    /// Finds the ID of the organization called `name`, which is needed
+    /// to create buckets in it. Fails with `Error::OrgNotFound` if the
+    /// token can't see an organization by that name.
+    pub fn org_id(&self, name: &str) -> ApiCall<String> {
+        let name = name.to_owned();
+        let id =
+            self.api(hyper::Method::Get, "/api/v2/orgs", &[("org", name.as_str())], None)
+            .and_then(response_to_json)
+            .and_then(move |list: Organizations| {
+                list.orgs.into_iter()
+                    .find(|org| org.name == name)
+                    .map(|org| org.id)
+                    .ok_or_else(|| Error::OrgNotFound(name.clone()))
+            });
+
+        ApiCall(Box::new(id))
+    }
+}
@@ -0,0 +1,118 @@
+//! The `/api/v2/dbrps` endpoints, which map the database and retention
+//! policy of 1.x queries and writes to buckets
+
+use futures::{self, Future};
+use hyper;
+use hyper::client::Connect;
+use serde_json;
+
+use {response_to_json, AsyncDb, Error};
+use super::ApiCall;
+
+/// Sends InfluxQL for `database` and `retention_policy` to a bucket
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DbrpMapping {
+    pub id: String,
+    pub database: String,
+    pub retention_policy: String,
+    /// Whether this is used when a query doesn't name a retention policy
+    pub default: bool,
+    #[serde(rename = "orgID")]
+    pub org_id: String,
+    #[serde(rename = "bucketID")]
+    pub bucket_id: String,
+}
+
+/// A mapping to create with `AsyncDb::create_dbrp_mapping`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NewDbrpMapping {
+    #[serde(rename = "orgID")]
+    org_id: String,
+    #[serde(rename = "bucketID")]
+    bucket_id: String,
+    database: String,
+    retention_policy: String,
+    default: bool,
+}
+
+impl NewDbrpMapping {
+    /// Maps `database` and `retention_policy` to the bucket with the ID
+    /// `bucket_id`. See `AsyncDb::org_id` to find the ID of an
+    /// organization.
+    pub fn new<O, B, D, R>(org_id: O, bucket_id: B, database: D, retention_policy: R) -> Self
+        where O: Into<String>,
+              B: Into<String>,
+              D: Into<String>,
+              R: Into<String>,
+    {
+        NewDbrpMapping {
+            org_id: org_id.into(),
+            bucket_id: bucket_id.into(),
+            database: database.into(),
+            retention_policy: retention_policy.into(),
+            default: false,
+        }
+    }
+
+    /// Uses this mapping for queries of the database that don't name a
+    /// retention policy
+    pub fn default(mut self, default: bool) -> Self {
+        self.default = default;
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct DbrpMappings {
+    content: Vec<DbrpMapping>,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    pub fn create_dbrp_mapping(&self, mapping: &NewDbrpMapping) -> ApiCall<DbrpMapping> {
+        let body = match serde_json::to_vec(mapping) {
+            Ok(body) => body,
+            Err(e) => return ApiCall(Box::new(futures::future::err(Error::Serde(e)))),
+        };
+
+        let mapping =
+            self.api(hyper::Method::Post, "/api/v2/dbrps", &[], Some(body))
+            .and_then(response_to_json);
+
+        ApiCall(Box::new(mapping))
+    }
+
+    /// Lists the mappings in the organization given to `AsyncDb::v2`,
+    /// only those for `database` if it is given
+    pub fn dbrp_mappings(&self, database: Option<&str>) -> ApiCall<Vec<DbrpMapping>> {
+        let mut query = vec![];
+        if let Some(ref org) = self.org {
+            query.push(("org", org.as_str()));
+        }
+        if let Some(database) = database {
+            query.push(("db", database));
+        }
+
+        let mappings =
+            self.api(hyper::Method::Get, "/api/v2/dbrps", &query, None)
+            .and_then(response_to_json)
+            .map(|list: DbrpMappings| list.content);
+
+        ApiCall(Box::new(mappings))
+    }
+
+    /// Deletes a mapping from the organization given to `AsyncDb::v2`
+    pub fn delete_dbrp_mapping(&self, id: &str) -> ApiCall<()> {
+        let mut query = vec![];
+        if let Some(ref org) = self.org {
+            query.push(("org", org.as_str()));
+        }
+
+        let deleted =
+            self.api_item(hyper::Method::Delete, "/api/v2/dbrps/", id, &query, None)
+            .map(|_| ());
+
+        ApiCall(Box::new(deleted))
+    }
+}
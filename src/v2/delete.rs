@@ -0,0 +1,132 @@
+//! The `/api/v2/delete` endpoint, which deletes points by time range and
+//! predicate rather than with InfluxQL
+
+use std::fmt;
+use std::time::SystemTime;
+
+use futures::{self, Future};
+use hyper;
+use hyper::client::Connect;
+use serde_json;
+
+use influxql::quote_identifier;
+use {rfc3339, AsyncDb, Error};
+use super::ApiCall;
+
+/// Which points `AsyncDb::delete_range` deletes from the time range.
+/// InfluxDB only supports tag equality joined with `AND`.
+///
+/// ```
+/// # use influxdb::v2::Predicate;
+/// let predicate = Predicate::new()
+///     .measurement("cpu")
+///     .tag("host", "server \"01\"");
+///
+/// assert_eq!(predicate.to_string(), r#""_measurement" = "cpu" AND "host" = "server \"01\"""#);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Predicate {
+    conditions: Vec<(String, String)>,
+}
+
+impl Predicate {
+    /// Matches every point
+    pub fn new() -> Self {
+        Predicate::default()
+    }
+
+    pub fn measurement<S>(self, measurement: S) -> Self
+        where S: Into<String>
+    {
+        self.tag("_measurement", measurement)
+    }
+
+    /// Only points where the tag `key` has exactly `value`
+    pub fn tag<K, V>(mut self, key: K, value: V) -> Self
+        where K: Into<String>,
+              V: Into<String>,
+    {
+        self.conditions.push((key.into(), value.into()));
+        self
+    }
+}
+
+/// The predicate as InfluxDB expects it. The predicate parser uses
+/// InfluxQL's quoting rules.
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &(ref key, ref value)) in self.conditions.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" AND ")?;
+            }
+            write!(f, "{} = {}", quote_identifier(key), quote_identifier(value))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct DeleteBody {
+    start: String,
+    stop: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    predicate: Option<String>,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    /// Deletes the points in the bucket given to `AsyncDb::v2` from
+    /// `start` up to and including `stop` that match `predicate`
+    pub fn delete_range(&self, start: SystemTime, stop: SystemTime, predicate: &Predicate) -> ApiCall<()> {
+        let body = DeleteBody {
+            start: rfc3339::format(start),
+            stop: rfc3339::format(stop),
+            predicate: if predicate.conditions.is_empty() { None } else { Some(predicate.to_string()) },
+        };
+        let body = match serde_json::to_vec(&body) {
+            Ok(body) => body,
+            Err(e) => return ApiCall(Box::new(futures::future::err(Error::Serde(e)))),
+        };
+
+        let mut query = vec![("bucket", self.name.as_str())];
+        if let Some(ref org) = self.org {
+            query.push(("org", org.as_str()));
+        }
+
+        let deleted =
+            self.api(hyper::Method::Post, "/api/v2/delete", &query, Some(body))
+            .map(|_| ());
+
+        ApiCall(Box::new(deleted))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Predicate;
+
+    #[test]
+    fn an_empty_predicate_matches_everything() {
+        assert_eq!(Predicate::new().to_string(), "");
+    }
+
+    #[test]
+    fn conditions_are_joined_with_and() {
+        let predicate = Predicate::new()
+            .measurement("cpu")
+            .tag("host", "server01")
+            .tag("region", "us-west");
+
+        assert_eq!(predicate.to_string(),
+                   r#""_measurement" = "cpu" AND "host" = "server01" AND "region" = "us-west""#);
+    }
+
+    #[test]
+    fn keys_and_values_are_escaped() {
+        let predicate = Predicate::new().tag(r#"odd "key""#, "back\\slash\nand\"quote\" OR 1=1");
+
+        assert_eq!(predicate.to_string(),
+                   r#""odd \"key\"" = "back\\slash\nand\"quote\" OR 1=1""#);
+    }
+}
@@ -0,0 +1,196 @@
+//! Support for InfluxDB 2.x, which organizes data into buckets owned
+//! by organizations and authenticates every request with a token.
+//!
+//! ```no_run
+//! extern crate influxdb;
+//! extern crate tokio_core;
+//!
+//! use influxdb::AsyncDb;
+//!
+//! fn main() {
+//!     let mut core = tokio_core::reactor::Core::new()
+//!         .expect("Unable to create reactor core");
+//!
+//!     let async_db = AsyncDb::v2(
+//!         core.handle(),
+//!         "http://localhost:8086/",
+//!         "my_org",
+//!         "my_bucket",
+//!         "my_token",
+//!     ).expect("Unable to create AsyncDb");
+//!
+//!     let insert = async_db.add_data("cpu,host=server01 value=0.64");
+//!     core.run(insert).expect("Unable to run future to completion");
+//! }
+//! ```
+//!
+//! Buckets, organizations, tokens and the database and retention policy
+//! mappings used by InfluxQL can be managed through the same `AsyncDb`;
+//! see `AsyncDb::create_bucket`, `AsyncDb::create_authorization`,
+//! `AsyncDb::create_dbrp_mapping` and friends.
+
+use futures::{self, Future};
+use hyper;
+use hyper::client::{self, Connect};
+use tokio_core::reactor::Handle;
+use url;
+
+use version::WriteApi;
+use {check_response_code, AsyncDb, Error, Result};
+
+mod authorizations;
+mod buckets;
+mod dbrps;
+mod delete;
+
+pub use self::authorizations::{Action, Authorization, NewAuthorization, Permission, Resource, Status, Token};
+pub use self::buckets::{Bucket, NewBucket, Organization, RetentionRule};
+pub use self::dbrps::{DbrpMapping, NewDbrpMapping};
+pub use self::delete::Predicate;
+
+impl AsyncDb {
+    /// Writes to `bucket` in InfluxDB 2.x, authenticating with `token`.
+    ///
+    /// Queries use the 1.x compatibility API, which needs the bucket to
+    /// be mapped to a database and retention policy; see
+    /// `AsyncDb::create_dbrp_mapping`.
+    pub fn v2(handle: Handle, base_url: &str, org: &str, bucket: &str, token: &str) -> Result<Self> {
+        let client = hyper::Client::configure().keep_alive(false).build(&handle);
+        AsyncDb::with_client_v2(client, base_url, org, bucket, token)
+    }
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    pub(crate) fn with_client_v2(client: hyper::Client<C>,
+                                 base_url: &str,
+                                 org: &str,
+                                 bucket: &str,
+                                 token: &str)
+                                 -> Result<Self>
+    {
+        let mut db = AsyncDb::with_client(client, base_url, bucket)?;
+        db.org = Some(org.into());
+        db.token = Some(token.into());
+        db.set_write_api(WriteApi::V2)?;
+        Ok(db)
+    }
+
+    /// The organization, when talking to InfluxDB 2.x
+    pub fn org(&self) -> Option<&str> {
+        self.org.as_ref().map(|org| org.as_str())
+    }
+
+    /// Sends an authenticated request to `path` of the 2.x API
+    fn api(&self,
+           method: hyper::Method,
+           path: &str,
+           query: &[(&str, &str)],
+           body: Option<Vec<u8>>)
+           -> Box<Future<Item = client::Response, Error = Error>>
+    {
+        match self.base_url.join(path) {
+            Ok(url) => self.api_url(method, url, query, body),
+            Err(e) => Box::new(futures::future::err(e.into())),
+        }
+    }
+
+    /// Sends an authenticated request to the item of `collection`
+    /// called `id`, such as `/api/v2/buckets/` and a bucket ID. The ID
+    /// is percent-encoded, so it stays a single path segment.
+    fn api_item(&self,
+                method: hyper::Method,
+                collection: &str,
+                id: &str,
+                query: &[(&str, &str)],
+                body: Option<Vec<u8>>)
+                -> Box<Future<Item = client::Response, Error = Error>>
+    {
+        let mut url = match self.base_url.join(collection) {
+            Ok(url) => url,
+            Err(e) => return Box::new(futures::future::err(e.into())),
+        };
+        url.path_segments_mut()
+            .expect("The 2.x API is always under an HTTP URL")
+            .pop_if_empty()
+            .push(id);
+        self.api_url(method, url, query, body)
+    }
+
+    fn api_url(&self,
+               method: hyper::Method,
+               mut url: url::Url,
+               query: &[(&str, &str)],
+               body: Option<Vec<u8>>)
+               -> Box<Future<Item = client::Response, Error = Error>>
+    {
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+
+        let mut request = self.request(method, &url);
+        if let Some(body) = body {
+            request.headers_mut().set(hyper::header::ContentType::json());
+            request.set_body(body);
+        }
+
+        let response =
+            self.client.request(request)
+            .map_err(Error::Hyper)
+            .and_then(check_response_code);
+
+        Box::new(response)
+    }
+}
+
+/// Which part of a list to fetch. The 2.x API returns at most `limit`
+/// items, so keep asking for the `next` page until a shorter one comes
+/// back.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Page {
+    pub fn first(limit: usize) -> Self {
+        Page {
+            offset: 0,
+            limit: limit,
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        Page {
+            offset: self.offset + self.limit,
+            limit: self.limit,
+        }
+    }
+}
+
+impl Default for Page {
+    /// The first 20 items, as InfluxDB returns when not asked otherwise
+    fn default() -> Self {
+        Page::first(20)
+    }
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct ApiCall<T>(Box<Future<Item = T, Error = Error>>);
+
+impl<T> Future for ApiCall<T> {
+    type Item = T;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
+/// How InfluxDB 2.x describes a failed request
+#[derive(Debug, Deserialize)]
+pub struct ServerError {
+    pub code: String,
+    pub message: String,
+}
@@ -0,0 +1,290 @@
+//! Finding out which InfluxDB is on the other end, and what it can do.
+//!
+//! ```no_run
+//! extern crate influxdb;
+//! extern crate tokio_core;
+//!
+//! use influxdb::AsyncDb;
+//!
+//! fn main() {
+//!     let mut core = tokio_core::reactor::Core::new()
+//!         .expect("Unable to create reactor core");
+//!     let mut async_db = AsyncDb::new(core.handle(), "http://localhost:8086/", "my_database")
+//!         .expect("Unable to create AsyncDb");
+//!
+//!     let version = core.run(async_db.detect_version())
+//!         .expect("Unable to run future to completion");
+//!     println!("{:?} {} can run Flux: {}", version.flavor, version.version, version.capabilities().flux);
+//!     async_db.set_write_api(version.write_api()).expect("Unable to change the write API");
+//! }
+//! ```
+
+use futures::{self, Future};
+use hyper::{self, client};
+use hyper::client::Connect;
+
+use {check_response_code, response_to_json, AsyncDb, Error, Result};
+
+/// Which distribution of InfluxDB a server is
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Flavor {
+    /// The open source release
+    Oss,
+    /// InfluxDB Enterprise, which clusters 1.x
+    Enterprise,
+    /// InfluxDB Cloud
+    Cloud,
+}
+
+/// What a server said about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerVersion {
+    pub flavor: Flavor,
+    /// Such as `1.8.10` or `v2.7.1`, exactly as the server sent it
+    pub version: String,
+}
+
+/// What a server supports, from its flavor and version
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Unsigned integer fields, written with a `u` suffix
+    pub u64_fields: bool,
+    /// Flux queries; see `AsyncDb::flux_query`. 1.7 and 1.8 only run
+    /// them when `flux-enabled` is set, which can't be told from the
+    /// version, so they are reported as not supporting Flux.
+    pub flux: bool,
+    /// SQL queries; see `AsyncDb::sql_query`
+    pub sql: bool,
+    /// Whether requests are authenticated with a token rather than a
+    /// username and password
+    pub token_auth: bool,
+    /// The `/api/v2/write` endpoint
+    pub v2_write: bool,
+}
+
+/// Which HTTP API `AsyncDb::add_data` writes through
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WriteApi {
+    /// `/write`, with the database as `db`
+    V1,
+    /// `/api/v2/write`, with the database as `bucket`
+    V2,
+}
+
+impl ServerVersion {
+    fn new(build: Option<&str>, version: String) -> Self {
+        let flavor = match build.map(|build| build.to_lowercase()) {
+            Some(ref build) if build == "ent" || build == "enterprise" => Flavor::Enterprise,
+            Some(ref build) if build == "cloud" => Flavor::Cloud,
+            _ => Flavor::Oss,
+        };
+
+        ServerVersion {
+            flavor: flavor,
+            version: version,
+        }
+    }
+
+    /// The major and minor version, if the version can be understood
+    pub fn major_minor(&self) -> Option<(u32, u32)> {
+        let version = self.version.trim_start_matches('v');
+        let mut parts = version.split(|c: char| !c.is_ascii_digit());
+        let major = parts.next().and_then(|major| major.parse().ok())?;
+        let minor = parts.next().and_then(|minor| minor.parse().ok()).unwrap_or(0);
+        Some((major, minor))
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        let major_minor = match (self.flavor, self.major_minor()) {
+            (_, Some(major_minor)) => major_minor,
+            // Cloud has been 2.x compatible from the start
+            (Flavor::Cloud, None) => (2, 0),
+            (_, None) => (0, 0),
+        };
+
+        match major_minor {
+            (1, minor) => Capabilities {
+                u64_fields: false,
+                flux: false,
+                sql: false,
+                token_auth: false,
+                v2_write: minor >= 8,
+            },
+            (2, _) => Capabilities {
+                u64_fields: true,
+                flux: true,
+                sql: false,
+                token_auth: true,
+                v2_write: true,
+            },
+            (major, _) if major >= 3 => Capabilities {
+                u64_fields: true,
+                flux: false,
+                sql: true,
+                token_auth: true,
+                v2_write: true,
+            },
+            _ => Capabilities {
+                u64_fields: false,
+                flux: false,
+                sql: false,
+                token_auth: false,
+                v2_write: false,
+            },
+        }
+    }
+
+    /// The API that `AsyncDb::add_data` should use with this server:
+    /// `/api/v2/write` where tokens are used, `/write` otherwise
+    pub fn write_api(&self) -> WriteApi {
+        if self.capabilities().token_auth {
+            WriteApi::V2
+        } else {
+            WriteApi::V1
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Health {
+    version: Option<String>,
+}
+
+impl<C> AsyncDb<C>
+    where C: Connect
+{
+    /// Asks the server which InfluxDB it is, from the headers of
+    /// `/ping` or, for servers that don't send them, from `/health`
+    pub fn detect_version(&self) -> DetectVersion {
+        let (ping_endpoint, health_endpoint) = match (self.base_url.join("/ping"), self.base_url.join("/health")) {
+            (Ok(ping), Ok(health)) => (ping, health),
+            (Err(e), _) | (_, Err(e)) => return DetectVersion(Box::new(futures::future::err(e.into()))),
+        };
+
+        let health_request = self.request(hyper::Method::Get, &health_endpoint);
+        let client = self.client.clone();
+
+        let version =
+            self.client.request(self.request(hyper::Method::Get, &ping_endpoint))
+            .map_err(Error::Hyper)
+            .and_then(check_response_code)
+            .and_then(move |response| {
+                let build = header(&response, "X-Influxdb-Build");
+                match header(&response, "X-Influxdb-Version") {
+                    Some(version) => {
+                        let f = futures::future::ok(ServerVersion::new(build.as_ref().map(|b| b.as_str()), version));
+                        futures::future::Either::A(f)
+                    }
+                    None => {
+                        let f =
+                            client.request(health_request)
+                            .map_err(Error::Hyper)
+                            .and_then(check_response_code)
+                            .and_then(response_to_json)
+                            .and_then(move |health: Health| {
+                                let version = health.version.ok_or(Error::UnknownVersion)?;
+                                Ok(ServerVersion::new(build.as_ref().map(|b| b.as_str()), version))
+                            });
+                        futures::future::Either::B(f)
+                    }
+                }
+            });
+
+        DetectVersion(Box::new(version))
+    }
+
+    /// Writes through `api` from now on. `AsyncDb::v2` uses `V2`, and
+    /// `AsyncDb::new` uses `V1`; see `ServerVersion::write_api`.
+    pub fn set_write_api(&mut self, api: WriteApi) -> Result<()> {
+        let write_endpoint = match api {
+            WriteApi::V1 => {
+                let mut write_endpoint = self.base_url.join("/write")?;
+                write_endpoint.query_pairs_mut()
+                    .append_pair("db", &self.name);
+                write_endpoint
+            }
+            WriteApi::V2 => {
+                let mut write_endpoint = self.base_url.join("/api/v2/write")?;
+                {
+                    let mut pairs = write_endpoint.query_pairs_mut();
+                    if let Some(ref org) = self.org {
+                        pairs.append_pair("org", org);
+                    }
+                    pairs.append_pair("bucket", &self.name)
+                        .append_pair("precision", "ns");
+                }
+                write_endpoint
+            }
+        };
+
+        self.write_endpoint = write_endpoint;
+        Ok(())
+    }
+}
+
+fn header(response: &client::Response, name: &str) -> Option<String> {
+    response.headers().get_raw(name)
+        .and_then(|raw| raw.one())
+        .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+#[must_use = "futures do nothing unless polled"]
+pub struct DetectVersion(Box<Future<Item = ServerVersion, Error = Error>>);
+
+impl Future for DetectVersion {
+    type Item = ServerVersion;
+    type Error = Error;
+
+    fn poll(&mut self) -> futures::Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Flavor, ServerVersion, WriteApi};
+
+    fn version(build: Option<&str>, version: &str) -> ServerVersion {
+        ServerVersion::new(build, version.to_owned())
+    }
+
+    #[test]
+    fn flavors_come_from_the_build() {
+        assert_eq!(version(Some("OSS"), "1.8.10").flavor, Flavor::Oss);
+        assert_eq!(version(Some("ENT"), "1.11.3-c1.11.3").flavor, Flavor::Enterprise);
+        assert_eq!(version(Some("Cloud"), "2.0").flavor, Flavor::Cloud);
+        assert_eq!(version(None, "v2.7.1").flavor, Flavor::Oss);
+    }
+
+    #[test]
+    fn versions_are_understood_with_or_without_a_v() {
+        assert_eq!(version(None, "1.8.10").major_minor(), Some((1, 8)));
+        assert_eq!(version(None, "v2.7.1").major_minor(), Some((2, 7)));
+        assert_eq!(version(None, "3.0.0-nightly").major_minor(), Some((3, 0)));
+        assert_eq!(version(None, "unknown").major_minor(), None);
+    }
+
+    #[test]
+    fn capabilities_depend_on_the_version() {
+        let old = version(Some("OSS"), "1.6.4").capabilities();
+        assert!(!old.flux && !old.v2_write && !old.token_auth && !old.u64_fields);
+
+        let compatible = version(Some("OSS"), "1.8.10").capabilities();
+        assert!(!compatible.flux && compatible.v2_write && !compatible.token_auth);
+
+        let v2 = version(Some("OSS"), "v2.7.1").capabilities();
+        assert!(v2.flux && v2.v2_write && v2.token_auth && v2.u64_fields && !v2.sql);
+
+        let v3 = version(None, "3.0.1").capabilities();
+        assert!(v3.sql && v3.token_auth && !v3.flux);
+
+        assert!(version(Some("Cloud"), "cloud").capabilities().flux);
+    }
+
+    #[test]
+    fn tokens_mean_the_v2_write_api() {
+        assert_eq!(version(Some("ENT"), "1.11.3").write_api(), WriteApi::V1);
+        assert_eq!(version(Some("OSS"), "v2.7.1").write_api(), WriteApi::V2);
+    }
+}
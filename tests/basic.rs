@@ -1,8 +1,11 @@
+// These tests use the blocking client to set up and check each database.
+// With the `mock` feature they run against `influxdb::mock::MockServer`
+// instead of InfluxDB on localhost.
+#![cfg(feature = "blocking")]
+
 extern crate tokio_core;
 extern crate futures;
-extern crate serde;
 
-extern crate reqwest;
 #[macro_use]
 extern crate lazy_static;
 
@@ -11,18 +14,20 @@ extern crate influxdb;
 #[macro_use]
 extern crate influxdb_derive;
 
-use std::error::Error;
 use std::sync::Mutex;
 use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use futures::Future;
 
-use influxdb::{AsyncDb, AsyncUdpDb, QueryResponse, InfluxServerError};
+use influxdb::{AsyncDb, AsyncUdpDb, Precision, QueryResponse, SeriesFilter};
+use influxdb::blocking::Db;
 
+#[cfg(not(feature = "mock"))]
 const HTTP_BASE_URL: &'static str = "http://localhost:8086/";
 
+#[cfg(not(feature = "mock"))]
 const UDP_IP_AND_PORT: &'static str = "127.0.0.1:8089";
 const UDP_DB_NAME: &'static str = "influxdb_rs_udp";
 const UDP_BATCH_TIMEOUT_MS: u64 = 50;
@@ -35,7 +40,7 @@ fn query_asynchronously() {
         .unwrap();
 
     let response = with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, &db.name).unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
 
         async_db.query(r#"SELECT "value","host" FROM "cpu_load_short" WHERE "region"='us-west'"#)
     });
@@ -45,12 +50,33 @@ fn query_asynchronously() {
     assert_eq!(response.results[0].series[0].values[0][2].as_str(), Some("server01"));
 }
 
+#[test]
+fn query_with_epoch() {
+    let db = fresh_db();
+
+    db.add_data("cpu_load_short,host=server01,region=us-west value=0.64 1434055562000000000")
+        .unwrap();
+
+    let response = with_core(|core| {
+        let mut async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
+        async_db.set_epoch(Some(Precision::Seconds));
+
+        async_db.query(r#"SELECT "value" FROM "cpu_load_short""#)
+    });
+
+    let series = &response.results[0].series[0];
+    assert_eq!(series.epoch_times().unwrap(), vec![1434055562]);
+    assert_eq!(series.epoch, Some(Precision::Seconds));
+    assert_eq!(series.system_times().unwrap(),
+               vec![UNIX_EPOCH + Duration::from_secs(1434055562)]);
+}
+
 #[test]
 fn add_data_asynchronously() {
     let db = fresh_db();
 
     with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, &db.name).unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
 
         async_db.add_data("cpu_load_short,host=server01,region=us-west value=0.64 1434055562000000000")
     });
@@ -68,7 +94,7 @@ fn add_batched_data_asynchronously() {
     let db = fresh_db();
 
     with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, &db.name).unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
 
         async_db.add_data(r#"
 cpu_load_short,host=server01,region=us-west value=0.64 1434055562000000000
@@ -105,7 +131,7 @@ fn add_data_asynchronously_udp() {
 #[ignore] // TODO: handle errors returned in the response instead of status code
 fn query_nonexistent_db() {
     let response = with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, "does_not_exist").unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), "does_not_exist").unwrap();
 
         async_db.query(r#"SELECT "value","host" FROM "cpu_load_short" WHERE "region"='us-west'"#)
     });
@@ -125,7 +151,7 @@ fn multiple_queries() {
         .unwrap();
 
     let mut response = with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, &db.name).unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
 
         async_db.query(r#"SELECT "value","host" FROM "cpu_load_short" WHERE "region"='us-west'; SELECT "value" FROM "cpu_load_short" WHERE "host"='server01'"#)
     });
@@ -146,7 +172,7 @@ fn multiple_queries() {
 #[test]
 fn multiple_queries_to_nonexistent_database() {
     let response = with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, "does_not_exist").unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), "does_not_exist").unwrap();
 
         async_db.query(r#"SELECT "value","host" FROM "cpu_load_short" WHERE "region"='us-west'; SELECT "value" FROM "cpu_load_short" WHERE "host"='server01'"#)
     });
@@ -157,6 +183,114 @@ fn multiple_queries_to_nonexistent_database() {
     assert_eq!(response.results[0].error, Some(String::from("not executed")));
 }
 
+#[test]
+fn batch_queries_to_nonexistent_database() {
+    let results = with_core(|core| {
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), "does_not_exist").unwrap();
+
+        async_db.query_batch(&[
+            r#"SELECT "value","host" FROM "cpu_load_short" WHERE "region"='us-west'"#,
+            r#"SELECT "value" FROM "cpu_load_short" WHERE "host"='server01'"#,
+        ])
+    });
+
+    assert_eq!(results.len(), 2);
+    match results[0] {
+        Err(influxdb::Error::Statement(_)) => {}
+        ref other => panic!("Unexpected result: {:?}", other),
+    }
+    match results[1] {
+        Err(influxdb::Error::NotExecuted) => {}
+        ref other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn batch_queries_pair_each_statement_with_its_result() {
+    let db = fresh_db();
+
+    db.add_data("cpu_load_short,host=server01,region=us-west value=0.64 1434055562000000000")
+        .unwrap();
+    db.add_data("cpu_load_short,host=server02,region=us-east value=0.8 1434055563000000000")
+        .unwrap();
+
+    let results = db.db.query_batch(vec![
+        r#"SELECT "value" FROM "cpu_load_short" WHERE "region"='us-west'"#,
+        r#"SELECT "value" FROM "cpu_load_short" WHERE "region"='us-east'"#,
+        r#"SELECT "value" FROM "no_such_measurement""#,
+    ]).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().unwrap()[0].values[0][1].as_f64(), Some(0.64));
+    assert_eq!(results[1].as_ref().unwrap()[0].values[0][1].as_f64(), Some(0.8));
+    assert_eq!(results[2].as_ref().unwrap().len(), 0);
+}
+
+#[test]
+fn delete_by_tag() {
+    let db = fresh_db();
+
+    db.add_data("cpu_load_short,host=server01,customer=acme value=0.64 1434055562000000000")
+        .unwrap();
+    db.add_data("cpu_load_short,host=server02,customer=other value=0.8 1434055562000000000")
+        .unwrap();
+
+    // A value that would match everything if it weren't escaped
+    let injection = SeriesFilter::new().tag("customer", "x' OR 'a' = 'a");
+    db.db.delete(&injection).unwrap();
+
+    let acme = SeriesFilter::new().measurement("cpu_load_short").tag("customer", "acme");
+    db.db.delete(&acme).unwrap();
+
+    let response = db.query(r#"SELECT "value", "host" FROM "cpu_load_short""#).unwrap();
+
+    assert_eq!(response.results[0].series[0].values.len(), 1);
+    assert_eq!(response.results[0].series[0].values[0][2].as_str(), Some("server02"));
+}
+
+#[test]
+fn drop_measurement() {
+    let db = fresh_db();
+
+    db.add_data("cpu_load_short,host=server01 value=0.64 1434055562000000000")
+        .unwrap();
+    db.add_data("rpm,host=server01 value=1434 1434055562000000000")
+        .unwrap();
+
+    with_core(|core| {
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
+
+        async_db.drop_measurement("cpu_load_short")
+    });
+
+    let response = db.query("SHOW MEASUREMENTS").unwrap();
+
+    assert_eq!(response.results[0].series[0].values.len(), 1);
+    assert_eq!(response.results[0].series[0].values[0][0].as_str(), Some("rpm"));
+}
+
+#[test]
+fn drop_measurement_from_nonexistent_database() {
+    let result = with_core(|core| {
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), "does_not_exist").unwrap();
+
+        async_db.drop_measurement("cpu_load_short").then(Ok::<_, ()>)
+    });
+
+    match result {
+        Err(influxdb::Error::Statement(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn drop_nonexistent_shard() {
+    let db = fresh_db();
+
+    // InfluxDB doesn't mind
+    db.db.drop_shard(u64::max_value()).unwrap();
+}
+
 #[derive(Measurement)]
 #[influx(rename = "cpu_load_short")]
 struct CpuLoadShort {
@@ -165,7 +299,7 @@ struct CpuLoadShort {
     #[influx(rename = "host")]
     hostname: &'static str,
     // Multiple values in one attribute
-    #[influx(tag, field)]
+    #[influx(tag, rename = "region")]
     region: &'static str,
     #[influx(field)]
     value: f64,
@@ -185,7 +319,7 @@ fn typed_insert() {
     let db = fresh_db();
 
     with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, &db.name).unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
 
         async_db.add_data(item)
     });
@@ -225,7 +359,7 @@ fn batched_typed_insert() {
     let db = fresh_db();
 
     with_core(|core| {
-        let async_db = AsyncDb::new(core.handle(), HTTP_BASE_URL, &db.name).unwrap();
+        let async_db = AsyncDb::new(core.handle(), &http_base_url(), &db.name).unwrap();
 
         async_db.add_data(&items)
     });
@@ -255,6 +389,36 @@ fn test_infrastructure() {
     assert_eq!(response.results[0].series[0].values[0][2].as_str(), Some("server01"));
 }
 
+#[cfg(feature = "mock")]
+lazy_static! {
+    static ref SERVER: influxdb::mock::MockServer = {
+        let server = influxdb::mock::MockServer::start()
+            .expect("Unable to start the mock server");
+        server.set_udp_database(UDP_DB_NAME);
+        server
+    };
+}
+
+#[cfg(feature = "mock")]
+fn http_base_url() -> String {
+    SERVER.base_url()
+}
+
+#[cfg(not(feature = "mock"))]
+fn http_base_url() -> String {
+    String::from(HTTP_BASE_URL)
+}
+
+#[cfg(feature = "mock")]
+fn udp_ip_and_port() -> String {
+    SERVER.udp_addr().to_string()
+}
+
+#[cfg(not(feature = "mock"))]
+fn udp_ip_and_port() -> String {
+    String::from(UDP_IP_AND_PORT)
+}
+
 fn fresh_db() -> TestingDb {
     static DB_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
 
@@ -266,7 +430,7 @@ fn fresh_db() -> TestingDb {
 fn fresh_db_named<S>(name: S) -> TestingDb
     where S: Into<String>,
 {
-    let db = TestingDb::new(HTTP_BASE_URL, name)
+    let db = TestingDb::new(&http_base_url(), name)
         .expect("Unable to create test database");
     db.create_db()
         .expect("Unable to create test database");
@@ -294,7 +458,7 @@ fn with_udp_db<F, T>(f: F) -> TestingDb
     let test_db = fresh_db_named(UDP_DB_NAME);
 
     with_core(|core| {
-        let db = AsyncUdpDb::new(core.handle(), UDP_IP_AND_PORT)
+        let db = AsyncUdpDb::new(core.handle(), &udp_ip_and_port())
             .expect("Unable to create UDP database");
         f(db)
     });
@@ -317,102 +481,39 @@ fn with_core<F, T>(f: F) -> T::Item
 
 struct TestingDb {
     name: String,
-    client: reqwest::Client,
-    query_endpoint: reqwest::Url,
-    write_endpoint: reqwest::Url,
+    db: Db,
 }
 
 impl TestingDb {
     /// `base_url` should include the protocol, host, and port (`http://localhost:8086`)
-    fn new<S>(base_url: &str, name: S) -> Result<Self, Box<Error>>
+    fn new<S>(base_url: &str, name: S) -> influxdb::Result<Self>
         where S: Into<String>,
     {
         let name = name.into();
-
-        let base_url = reqwest::Url::parse(base_url)?;
-        let query_endpoint = base_url.join("/query")?;
-        let mut write_endpoint = base_url.join("/write")?;
-        write_endpoint.query_pairs_mut()
-            .append_pair("db", &name);
-
-        let client = reqwest::Client::new()?;
+        let db = Db::new(base_url, &name)?;
 
         Ok(TestingDb {
             name: name,
-            client: client,
-            query_endpoint: query_endpoint,
-            write_endpoint: write_endpoint,
+            db: db,
         })
     }
 
-    fn create_db(&self) -> Result<(), Box<Error>> {
-        let mut u = self.query_endpoint.clone();
-        u.query_pairs_mut()
-            .append_pair("q", &format!("CREATE DATABASE {}", self.name));
-        let mut res = self.client.post(u).send()?;
-
-        check_result(&mut res)?;
-        Ok(())
+    fn create_db(&self) -> influxdb::Result<()> {
+        self.db.create_database()
     }
 
-    fn drop_db(&self) -> Result<(), Box<Error>> {
-        let mut u = self.query_endpoint.clone();
-        u.query_pairs_mut()
-            .append_pair("q", &format!("DROP DATABASE {}", self.name));
-        let mut res = self.client.post(u).send()?;
-
-        check_result(&mut res)?;
-        Ok(())
-    }
-
-    fn add_data(&self, data: &str) -> Result<(), Box<Error>> {
-        let mut res = self.client.post(self.write_endpoint.clone())
-            .body(data)
-            .send()?;
-
-        check_result(&mut res)?;
-        Ok(())
+    fn add_data(&self, data: &str) -> influxdb::Result<()> {
+        self.db.add_data(data)
     }
 
-    fn query<T>(&self, query: &str) -> Result<T, Box<Error>>
-        where T: serde::Deserialize,
-    {
-        let mut u = self.query_endpoint.clone();
-        u.query_pairs_mut()
-            .append_pair("db", &self.name)
-            .append_pair("q", query);
-        let mut res = self.client.get(u).send()?;
-
-        check_result(&mut res)?;
-        let val = res.json()?;
-        Ok(val)
+    fn query(&self, query: &str) -> influxdb::Result<QueryResponse> {
+        self.db.query(query)
     }
 }
 
 impl Drop for TestingDb {
     fn drop(&mut self) {
         // Influx does not care if we drop a database that doesn't exist
-        self.drop_db().unwrap_or_else(|e| panic!("Unable to drop database {}: {}", self.name, e));
-    }
-}
-
-fn check_result(res: &mut reqwest::Response) -> Result<(), Box<Error>> {
-    let status = *res.status();
-
-    if status.is_success() {
-        Ok(())
-    } else if status.is_client_error() {
-        Err(parse_influx_server_error(res, "Client error").into())
-    } else if status.is_server_error() {
-        Err(parse_influx_server_error(res, "Server error").into())
-    } else {
-        Err(parse_influx_server_error(res, "Unknown error").into())
-    }
-}
-
-fn parse_influx_server_error(res: &mut reqwest::Response, kind: &str) -> String {
-    match res.json::<InfluxServerError>() {
-        Ok(e) => format!("{}: {}", kind, e.error),
-        Err(_) => format!("{}: (unable to parse error message)", kind),
+        self.db.drop_database().unwrap_or_else(|e| panic!("Unable to drop database {}: {}", self.name, e));
     }
 }
@@ -0,0 +1,292 @@
+extern crate influxdb;
+
+#[macro_use]
+extern crate influxdb_derive;
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+
+use influxdb::Measurement;
+use influxdb::measurement::TagValue;
+
+#[derive(Measurement)]
+#[influx(rename = "sensor")]
+struct Sensor {
+    #[influx(tag)]
+    host: &'static str,
+    #[influx(tag)]
+    room: Option<String>,
+    #[influx(field)]
+    temperature: Option<f64>,
+    #[influx(field)]
+    humidity: Option<f64>,
+}
+
+#[derive(Measurement)]
+#[influx(rename = "sensor")]
+struct SensorWithCount {
+    #[influx(field)]
+    temperature: Option<f64>,
+    #[influx(field)]
+    count: i32,
+}
+
+fn to_data<T>(item: T) -> String
+    where T: Measurement,
+{
+    let mut s = String::new();
+    item.to_data(&mut s);
+    s
+}
+
+#[test]
+fn optional_tags_and_fields_are_included_when_present() {
+    let item = Sensor {
+        host: "server01",
+        room: Some(String::from("lab")),
+        temperature: Some(21.5),
+        humidity: Some(0.4),
+    };
+
+    assert_eq!(to_data(item), "sensor,host=server01,room=lab temperature=21.5,humidity=0.4 ");
+}
+
+#[test]
+fn optional_tags_are_omitted_when_none() {
+    let item = Sensor {
+        host: "server01",
+        room: None,
+        temperature: Some(21.5),
+        humidity: Some(0.4),
+    };
+
+    assert_eq!(to_data(item), "sensor,host=server01 temperature=21.5,humidity=0.4 ");
+}
+
+#[test]
+fn leading_optional_fields_do_not_leave_a_separator() {
+    let item = Sensor {
+        host: "server01",
+        room: None,
+        temperature: None,
+        humidity: Some(0.4),
+    };
+
+    assert_eq!(to_data(item), "sensor,host=server01 humidity=0.4 ");
+}
+
+#[test]
+fn trailing_optional_fields_do_not_leave_a_separator() {
+    let item = Sensor {
+        host: "server01",
+        room: None,
+        temperature: Some(21.5),
+        humidity: None,
+    };
+
+    assert_eq!(to_data(item), "sensor,host=server01 temperature=21.5 ");
+}
+
+#[test]
+fn optional_and_required_fields_can_be_mixed() {
+    assert_eq!(to_data(SensorWithCount { temperature: None, count: 3 }), "sensor count=3i ");
+    assert_eq!(to_data(SensorWithCount { temperature: Some(1.5), count: 3 }),
+               "sensor temperature=1.5,count=3i ");
+}
+
+#[test]
+fn all_fields_absent_is_an_error() {
+    let item = Sensor {
+        host: "server01",
+        room: None,
+        temperature: None,
+        humidity: None,
+    };
+
+    let mut s = String::new();
+    match item.try_to_data(&mut s) {
+        Err(influxdb::Error::NoFields(name)) => assert_eq!(name, "sensor"),
+        other => panic!("Expected an error, got {:?}", other),
+    }
+}
+
+#[test]
+#[should_panic(expected = "Unable to serialize sensor")]
+fn to_data_panics_when_serialization_fails() {
+    let item = Sensor {
+        host: "server01",
+        room: None,
+        temperature: Some(std::f64::NAN),
+        humidity: None,
+    };
+
+    to_data(item);
+}
+
+#[derive(Measurement)]
+#[influx(rename = "disk usage,by host")]
+struct DiskUsage {
+    #[influx(tag, rename = "host name")]
+    host: &'static str,
+    #[influx(field)]
+    path: String,
+}
+
+#[test]
+fn names_and_values_are_escaped() {
+    let item = DiskUsage { host: "server 01", path: String::from(r#"C:\"Program Files""#) };
+
+    assert_eq!(to_data(item),
+               r#"disk\ usage\,by\ host,host\ name=server\ 01 path="C:\\\"Program Files\"" "#);
+}
+
+#[test]
+fn invalid_values_are_errors() {
+    let item = DiskUsage { host: "server\n01", path: String::new() };
+
+    let mut s = String::new();
+    match item.try_to_data(&mut s) {
+        Err(influxdb::Error::Unescapable(value)) => assert_eq!(value, "server\n01"),
+        other => panic!("Expected an error, got {:?}", other),
+    }
+}
+
+#[derive(Measurement)]
+#[influx(rename = "reading")]
+struct Reading<'a> {
+    #[influx(tag)]
+    host: &'a str,
+    #[influx(field)]
+    value: f64,
+}
+
+#[derive(Measurement)]
+#[influx(rename = "stat")]
+struct Stat<T> {
+    #[influx(field)]
+    value: T,
+    #[influx(field)]
+    previous: Option<T>,
+}
+
+#[derive(Measurement)]
+#[influx(rename = "stat")]
+struct BoundedStat<'a, T: Copy + 'a, U>
+    where U: Clone,
+{
+    #[influx(tag)]
+    host: &'a str,
+    #[influx(field)]
+    value: &'a T,
+    #[influx(field)]
+    count: U,
+    // Not sent to InfluxDB, so needs no `FieldValue` bound
+    #[allow(dead_code)]
+    other: Vec<U>,
+}
+
+#[test]
+fn lifetime_parameterized_structs_can_be_serialized() {
+    let host = String::from("server01");
+    let item = Reading { host: &host, value: 0.5 };
+
+    assert_eq!(to_data(item), "reading,host=server01 value=0.5 ");
+}
+
+#[test]
+fn type_parameterized_structs_can_be_serialized() {
+    assert_eq!(to_data(Stat { value: 3i32, previous: Some(2) }), "stat value=3i,previous=2i ");
+    assert_eq!(to_data(Stat { value: 0.5f64, previous: None }), "stat value=0.5 ");
+}
+
+#[test]
+fn where_clauses_are_preserved() {
+    let value = 7u8;
+    let item = BoundedStat { host: "server01", value: &value, count: 3i64, other: vec![] };
+
+    assert_eq!(to_data(item), "stat,host=server01 value=7i,count=3i ");
+}
+
+#[allow(dead_code)]
+enum Region {
+    UsEast,
+    UsWest,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Region::UsEast => f.write_str("us-east"),
+            Region::UsWest => f.write_str("us-west"),
+        }
+    }
+}
+
+/// Doesn't implement `Display`, so implements `TagValue` directly
+struct Rack(u8, u8);
+
+impl TagValue for Rack {
+    fn append(&self, data: &mut String) -> influxdb::Result<()> {
+        data.push_str(&format!("r{}-{}", self.0, self.1));
+        Ok(())
+    }
+}
+
+#[derive(Measurement)]
+#[influx(rename = "traffic")]
+struct Traffic<S> {
+    #[influx(tag)]
+    region: Region,
+    #[influx(tag)]
+    shard: u32,
+    #[influx(tag)]
+    address: IpAddr,
+    #[influx(tag)]
+    rack: Option<Rack>,
+    #[influx(tag)]
+    source: S,
+    #[influx(field)]
+    bytes: i64,
+}
+
+#[test]
+fn non_string_tags_can_be_serialized() {
+    let item = Traffic {
+        region: Region::UsEast,
+        shard: 12,
+        address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        rack: Some(Rack(3, 4)),
+        source: true,
+        bytes: 1024,
+    };
+
+    assert_eq!(to_data(item),
+               "traffic,region=us-east,shard=12,address=10.0.0.1,rack=r3-4,source=true bytes=1024i ");
+}
+
+#[derive(Measurement)]
+#[influx(rename = "event")]
+struct Event {
+    #[influx(field)]
+    value: i32,
+    #[influx(timestamp)]
+    when: i64,
+}
+
+#[test]
+fn integer_timestamps_can_be_serialized() {
+    assert_eq!(to_data(Event { value: 1, when: -5 }), "event value=1i -5");
+}
+
+#[test]
+fn measurements_can_be_serialized_to_bytes() {
+    let batch = vec![
+        Event { value: 1, when: 10 },
+        Event { value: 2, when: 20 },
+    ];
+
+    let mut bytes = Vec::new();
+    batch.to_bytes(&mut bytes).unwrap();
+
+    assert_eq!(bytes, &b"event value=1i 10\nevent value=2i 20\n"[..]);
+}
@@ -0,0 +1,77 @@
+#![cfg(all(feature = "blocking", feature = "mock"))]
+
+extern crate influxdb;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use influxdb::Error;
+use influxdb::blocking::Db;
+use influxdb::mock::{MockServer, Value};
+
+fn server_with_db(name: &str) -> (MockServer, Db) {
+    let server = MockServer::start().unwrap();
+    server.create_database(name);
+    let db = Db::new(&server.base_url(), name).unwrap();
+    (server, db)
+}
+
+#[test]
+fn written_points_can_be_inspected() {
+    let (server, db) = server_with_db("mydb");
+
+    db.add_data("cpu,host=server01 value=0.64,count=3i 1434055562000000000").unwrap();
+
+    let points = server.points("mydb");
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].measurement, "cpu");
+    assert_eq!(points[0].tags["host"], "server01");
+    assert_eq!(points[0].fields["value"], Value::Float(0.64));
+    assert_eq!(points[0].fields["count"], Value::Integer(3));
+    assert_eq!(points[0].timestamp, 1434055562000000000);
+}
+
+#[test]
+fn queued_failures_answer_the_next_requests() {
+    let (server, db) = server_with_db("mydb");
+    server.fail_next(500, "timeout");
+
+    match db.add_data("cpu value=1") {
+        Err(Error::BadRequest(ref message)) => assert_eq!(message, "timeout"),
+        other => panic!("expected the queued failure, got {:?}", other),
+    }
+    db.add_data("cpu value=2").unwrap();
+
+    assert_eq!(server.points("mydb").len(), 1);
+}
+
+#[test]
+fn requests_are_delayed_by_the_latency() {
+    let (server, db) = server_with_db("mydb");
+    server.set_latency(Duration::from_millis(100));
+
+    let start = Instant::now();
+    db.query("SHOW DATABASES").unwrap();
+
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn statements_that_change_the_server_must_be_posted() {
+    let (server, db) = server_with_db("mydb");
+    let address = server.base_url().trim_start_matches("http://").trim_end_matches('/').to_owned();
+
+    let mut stream = TcpStream::connect(&address[..]).unwrap();
+    stream.write_all(b"GET /query?db=mydb&q=DROP+DATABASE+mydb HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(response.starts_with("HTTP/1.1 405 "), "{}", response);
+    assert!(response.ends_with(r#"{"error":"DROP DATABASE requires POST"}"#), "{}", response);
+    assert_eq!(server.databases(), ["mydb"]);
+
+    // The client POSTs it instead
+    db.drop_database().unwrap();
+    assert!(server.databases().is_empty());
+}
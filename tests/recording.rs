@@ -0,0 +1,148 @@
+#![cfg(feature = "mock")]
+
+extern crate tokio_core;
+extern crate futures;
+
+extern crate influxdb;
+
+use futures::Future;
+
+use influxdb::{AsyncDb, AsyncUdpDb, Error};
+use influxdb::mock::{Recorder, Value};
+
+#[test]
+fn writes_are_recorded_as_points() {
+    let recorder = Recorder::new();
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::recording(core.handle(), &recorder, "mydb").unwrap();
+
+    core.run(db.add_data("cpu,host=server01 value=0.64 1434055562000000000\nmem used=3i 1434055562000000000"))
+        .unwrap();
+
+    let requests = recorder.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].endpoint, "/write");
+    assert_eq!(requests[0].param("db"), Some("mydb"));
+
+    let points = recorder.points();
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].measurement, "cpu");
+    assert_eq!(points[0].tags["host"], "server01");
+    assert_eq!(points[0].fields["value"], Value::Float(0.64));
+    assert_eq!(points[1].fields["used"], Value::Integer(3));
+    assert_eq!(points[1].timestamp, 1434055562000000000);
+}
+
+#[test]
+fn v2_writes_are_recorded_with_the_bucket() {
+    let recorder = Recorder::new();
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::recording_v2(core.handle(), &recorder, "myorg", "mybucket", "secret").unwrap();
+
+    core.run(db.add_data("cpu value=1 1")).unwrap();
+
+    let requests = recorder.requests();
+    assert_eq!(requests[0].endpoint, "/api/v2/write");
+    assert_eq!(requests[0].param("org"), Some("myorg"));
+    assert_eq!(requests[0].param("bucket"), Some("mybucket"));
+    assert_eq!(requests[0].points[0].timestamp, 1);
+}
+
+#[test]
+fn v2_clients_do_not_create_or_drop_databases() {
+    let recorder = Recorder::new();
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::recording_v2(core.handle(), &recorder, "myorg", "mybucket", "secret").unwrap();
+
+    match core.run(db.create_database()) {
+        Err(Error::UnsupportedByV2(what)) => assert_eq!(what, "CREATE DATABASE"),
+        other => panic!("expected CREATE DATABASE to be refused, got {:?}", other),
+    }
+    match core.run(db.drop_database()) {
+        Err(Error::UnsupportedByV2(what)) => assert_eq!(what, "DROP DATABASE"),
+        other => panic!("expected DROP DATABASE to be refused, got {:?}", other),
+    }
+    assert!(recorder.requests().is_empty());
+}
+
+#[test]
+fn unparseable_writes_are_rejected() {
+    let recorder = Recorder::new();
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::recording(core.handle(), &recorder, "mydb").unwrap();
+
+    match core.run(db.add_data("cpu value=")) {
+        Err(Error::BadRequest(_)) => {}
+        other => panic!("expected the write to be rejected, got {:?}", other),
+    }
+    assert_eq!(recorder.requests().len(), 1);
+    assert!(recorder.points().is_empty());
+}
+
+#[test]
+fn queries_are_answered_from_the_script() {
+    let recorder = Recorder::new();
+    recorder.respond(200,
+                     r#"{"results":[{"statement_id":0,"series":[{"name":"cpu","columns":["time","value"],
+                                                                 "values":[["2015-06-11T20:46:02Z",0.64]]}]}]}"#);
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::recording(core.handle(), &recorder, "mydb").unwrap();
+
+    let response = core.run(db.query("SELECT value FROM cpu")).unwrap();
+    assert_eq!(response.results[0].series[0].name, "cpu");
+    assert_eq!(response.results[0].series[0].values[0][1].as_f64(), Some(0.64));
+
+    // Once the script runs out, statements succeed without results
+    core.run(db.create_database()).unwrap();
+
+    let requests = recorder.requests();
+    assert_eq!(requests[0].endpoint, "/query");
+    assert_eq!(requests[0].param("q"), Some("SELECT value FROM cpu"));
+    assert_eq!(requests[1].param("q"), Some(r#"CREATE DATABASE "mydb""#));
+}
+
+#[test]
+fn scripted_errors_fail_queries() {
+    let recorder = Recorder::new();
+    recorder.respond(401, r#"{"error":"authorization failed"}"#);
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::recording(core.handle(), &recorder, "mydb").unwrap();
+
+    match core.run(db.query("SHOW DATABASES")) {
+        Err(Error::BadRequest(ref message)) => assert_eq!(message, "authorization failed"),
+        other => panic!("expected the scripted error, got {:?}", other),
+    }
+}
+
+#[test]
+fn datagrams_are_recorded_as_they_are_split() {
+    let recorder = Recorder::new();
+    let mut db = AsyncUdpDb::recording(&recorder, "127.0.0.1:8089").unwrap();
+    db.set_max_payload_size(16);
+
+    db.add_data("cpu value=1 1\ncpu value=2 2\n").wait().unwrap();
+
+    let datagrams = recorder.datagrams();
+    assert_eq!(datagrams.len(), 2);
+    assert_eq!(datagrams[0].to, "127.0.0.1:8089".parse().unwrap());
+    assert_eq!(datagrams[0].body, b"cpu value=1 1\n");
+    assert_eq!(recorder.points().iter().map(|point| point.timestamp).collect::<Vec<_>>(), vec![1, 2]);
+}
+
+#[test]
+fn points_are_kept_in_the_order_they_were_sent() {
+    let recorder = Recorder::new();
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let http = AsyncDb::recording(core.handle(), &recorder, "mydb").unwrap();
+    let udp = AsyncUdpDb::recording(&recorder, "127.0.0.1:8089").unwrap();
+
+    udp.add_data("cpu value=1 1").wait().unwrap();
+    core.run(http.add_data("cpu value=2 2")).unwrap();
+    udp.add_data("cpu value=3 3").wait().unwrap();
+
+    assert_eq!(recorder.points().iter().map(|point| point.timestamp).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+    recorder.clear();
+    assert!(recorder.points().is_empty());
+}
@@ -0,0 +1,94 @@
+//! A stand-in HTTP server for tests that don't need a real InfluxDB
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+
+/// An empty `204 No Content` response, as InfluxDB sends for writes
+pub const NO_CONTENT: &'static str = "HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n";
+
+/// A response with a body
+pub fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+            status, content_type, body.len(), body)
+}
+
+/// Answers a single HTTP request on a local TCP port with `response`.
+/// Returns the base URL to use and the request that was received.
+pub fn serve_tcp(response: &str) -> (String, thread::JoinHandle<String>) {
+    let (base_url, server) = serve_tcp_each(&[response]);
+    let server = thread::spawn(move || server.join().unwrap().pop().unwrap());
+    (base_url, server)
+}
+
+/// Answers one request per response, in order, each on its own
+/// connection. Returns the base URL to use and the requests that were
+/// received.
+pub fn serve_tcp_each(responses: &[&str]) -> (String, thread::JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    let responses: Vec<_> = responses.iter().map(|response| response.to_string()).collect();
+
+    let server = thread::spawn(move || {
+        responses.iter()
+            .map(|response| {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+                answer(&mut stream, response)
+            })
+            .collect()
+    });
+
+    (base_url, server)
+}
+
+/// Reads one request from `stream` and writes `response` to it
+pub fn answer<S>(stream: &mut S, response: &str) -> String
+    where S: Read + Write,
+{
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !is_complete(&request) {
+        let len = stream.read(&mut buffer).unwrap();
+        assert!(len > 0, "The connection closed before the request was complete");
+        request.extend_from_slice(&buffer[..len]);
+    }
+
+    stream.write_all(response.as_bytes()).unwrap();
+    String::from_utf8(request).unwrap()
+}
+
+/// Whether the headers and the whole body have arrived
+fn is_complete(request: &[u8]) -> bool {
+    let request = String::from_utf8_lossy(request);
+    let header_end = match request.find("\r\n\r\n") {
+        Some(i) => i + 4,
+        None => return false,
+    };
+    let headers = &request[..header_end];
+
+    if header(headers, "transfer-encoding") == Some("chunked") {
+        request.ends_with("\r\n0\r\n\r\n")
+    } else {
+        let content_length = header(headers, "content-length")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        request.len() >= header_end + content_length
+    }
+}
+
+/// The value of the first header called `name`, ignoring case
+pub fn header<'a>(headers: &'a str, name: &str) -> Option<&'a str> {
+    headers.lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key.eq_ignore_ascii_case(name) => Some(value.trim()),
+                _ => None,
+            }
+        })
+        .next()
+}
@@ -0,0 +1,115 @@
+extern crate tokio_core;
+extern crate futures;
+
+extern crate influxdb;
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use futures::Future;
+
+use influxdb::{AsyncUdpDb, Error};
+
+#[test]
+fn one_socket_is_used_for_every_send() {
+    let listener = listen("127.0.0.1:0");
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncUdpDb::new(core.handle(), &address).unwrap();
+
+    let first = db.add_data("cpu value=1");
+    let second = db.add_data("cpu value=2");
+    core.run(first.join(second)).unwrap();
+    core.run(db.add_data("cpu value=3")).unwrap();
+
+    let received: Vec<_> = (0..3).map(|_| receive(&listener)).collect();
+
+    assert_eq!(received[0].0, "cpu value=1");
+    assert_eq!(received[1].0, "cpu value=2");
+    assert_eq!(received[2].0, "cpu value=3");
+    assert!(received.iter().all(|r| r.1 == received[0].1));
+    assert_eq!(received[0].1, db.local_addr().unwrap().port());
+}
+
+#[test]
+fn the_local_address_can_be_chosen() {
+    let listener = listen("127.0.0.1:0");
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncUdpDb::with_local_addr(core.handle(), "127.0.0.1:0", &address).unwrap();
+
+    assert!(db.local_addr().unwrap().ip().is_loopback());
+
+    core.run(db.add_data("cpu value=1")).unwrap();
+    assert_eq!(receive(&listener).0, "cpu value=1");
+}
+
+#[test]
+fn ipv6_is_supported() {
+    let listener = match UdpSocket::bind("[::1]:0") {
+        Ok(listener) => listener,
+        // Not every machine has IPv6 configured
+        Err(_) => return,
+    };
+    listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncUdpDb::new(core.handle(), &address).unwrap();
+
+    assert!(db.local_addr().unwrap().is_ipv6());
+
+    core.run(db.add_data("cpu value=1")).unwrap();
+    assert_eq!(receive(&listener).0, "cpu value=1");
+}
+
+#[test]
+fn large_batches_are_split_between_points() {
+    let listener = listen("127.0.0.1:0");
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let mut db = AsyncUdpDb::new(core.handle(), &address).unwrap();
+    db.set_max_payload_size(23);
+
+    let points = vec!["cpu value=1", "cpu value=2", "cpu value=3", "memory value=4"];
+    core.run(db.add_data(points)).unwrap();
+
+    assert_eq!(receive(&listener).0, "cpu value=1\ncpu value=2");
+    assert_eq!(receive(&listener).0, "cpu value=3\n");
+    assert_eq!(receive(&listener).0, "memory value=4\n");
+}
+
+#[test]
+fn points_larger_than_the_payload_size_are_rejected() {
+    let listener = listen("127.0.0.1:0");
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let mut db = AsyncUdpDb::new(core.handle(), &address).unwrap();
+    db.set_max_payload_size(16);
+
+    match core.run(db.add_data(vec!["cpu value=1", "temperature value=2"])) {
+        Err(Error::PointTooLarge(19, 16)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    // Nothing is sent when any point is too large
+    core.run(db.add_data("cpu value=3")).unwrap();
+    assert_eq!(receive(&listener).0, "cpu value=3");
+}
+
+fn listen(address: &str) -> UdpSocket {
+    let listener = UdpSocket::bind(address).expect("Unable to bind test listener");
+    listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    listener
+}
+
+/// Returns the datagram and the port it came from
+fn receive(listener: &UdpSocket) -> (String, u16) {
+    let mut buffer = [0; 1024];
+    let (len, from) = listener.recv_from(&mut buffer).expect("Did not receive a datagram");
+    (String::from_utf8(buffer[..len].to_vec()).unwrap(), from.port())
+}
@@ -0,0 +1,126 @@
+#![cfg(unix)]
+
+extern crate tokio_core;
+extern crate futures;
+
+extern crate influxdb;
+
+mod support;
+
+use std::env;
+use std::fs;
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use influxdb::{AsyncDb, AsyncUnixDatagramDb, Error};
+
+#[test]
+fn writes_are_sent_over_http() {
+    let path = socket_path("http-write");
+    let server = serve(&path, support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::unix(core.handle(), &path, "mydb").unwrap();
+    core.run(db.add_data("cpu value=1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /write?db=mydb HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains("\r\ncpu value=1\r\n"), "{}", request);
+}
+
+#[test]
+fn queries_are_sent_over_http() {
+    let path = socket_path("http-query");
+    let body = r#"{"results":[{"statement_id":0,"series":[{"name":"cpu","columns":["time","value"],"values":[[0,1]]}]}]}"#;
+    let server = serve(&path, &support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::unix(core.handle(), &path, "mydb").unwrap();
+    let response = core.run(db.query("SELECT * FROM cpu")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /query?db=mydb&q=SELECT+*+FROM+cpu HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(response.results[0].series[0].name, "cpu");
+}
+
+#[test]
+fn changes_are_posted_in_a_form_body() {
+    let path = socket_path("http-post");
+    let body = r#"{"results":[{"statement_id":0}]}"#;
+    let server = serve(&path, &support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::unix(core.handle(), &path, "mydb").unwrap();
+    core.run(db.create_database()).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /query HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains("Content-Type: application/x-www-form-urlencoded\r\n"), "{}", request);
+    assert!(request.contains("db=mydb&q=CREATE+DATABASE+%22mydb%22"), "{}", request);
+}
+
+#[test]
+fn datagrams_are_sent_to_the_socket() {
+    let path = socket_path("datagram");
+    let listener = UnixDatagram::bind(&path).unwrap();
+    listener.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let mut db = AsyncUnixDatagramDb::new(core.handle(), &path).unwrap();
+    db.set_max_payload_size(24);
+
+    core.run(db.add_data("cpu value=1")).unwrap();
+    core.run(db.add_data(vec!["cpu value=2", "cpu value=3", "cpu value=4"])).unwrap();
+
+    assert_eq!(receive(&listener), "cpu value=1");
+    assert_eq!(receive(&listener), "cpu value=2\ncpu value=3\n");
+    assert_eq!(receive(&listener), "cpu value=4\n");
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn datagram_errors_are_reported() {
+    let path = socket_path("missing");
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncUnixDatagramDb::new(core.handle(), &path).unwrap();
+
+    match core.run(db.add_data("cpu value=1")) {
+        Err(Error::Unix(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+/// A fresh path in the temporary directory, unique to this process
+fn socket_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("influxdb-{}-{}.sock", process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+/// Answers a single HTTP request with `response`, returning the
+/// request that was received
+fn serve(path: &PathBuf, response: &str) -> thread::JoinHandle<String> {
+    let listener = UnixListener::bind(path).unwrap();
+    let path = path.clone();
+    let response = response.to_owned();
+
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let request = support::answer(&mut stream, &response);
+        fs::remove_file(&path).unwrap();
+        request
+    })
+}
+
+fn receive(listener: &UnixDatagram) -> String {
+    let mut buffer = [0; 1024];
+    let len = listener.recv(&mut buffer).expect("Did not receive a datagram");
+    String::from_utf8(buffer[..len].to_vec()).unwrap()
+}
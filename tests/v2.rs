@@ -0,0 +1,451 @@
+extern crate tokio_core;
+extern crate futures;
+extern crate serde_json;
+
+extern crate influxdb;
+
+mod support;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures::Stream;
+use influxdb::{AsyncDb, Error};
+use influxdb::flux::{FluxQuery, FluxValue};
+use influxdb::sql::{SqlFormat, SqlQuery};
+use influxdb::v2::{Action, NewAuthorization, NewBucket, NewDbrpMapping, Page, Permission, Predicate, RetentionRule, Status};
+
+#[test]
+fn writes_go_to_the_bucket_with_the_token() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my org", "my_bucket", "secret").unwrap();
+    core.run(db.add_data("cpu value=1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/write?org=my+org&bucket=my_bucket&precision=ns HTTP/1.1\r\n"),
+            "{}", request);
+    assert_eq!(support::header(&request, "authorization"), Some("Token secret"));
+    assert!(request.contains("\r\ncpu value=1\r\n"), "{}", request);
+    assert_eq!(db.org(), Some("my org"));
+}
+
+#[test]
+fn queries_are_authenticated() {
+    let body = r#"{"results":[{"statement_id":0}]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.query("SELECT * FROM cpu")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /query?db=my_bucket&q=SELECT+*+FROM+cpu HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(support::header(&request, "authorization"), Some("Token secret"));
+}
+
+#[test]
+fn v2_errors_are_reported() {
+    let body = r#"{"code":"unauthorized","message":"unauthorized access"}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("401 Unauthorized", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "wrong").unwrap();
+
+    match core.run(db.add_data("cpu value=1")) {
+        Err(Error::BadRequest(ref message)) if message == "unauthorized access" => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn v1_errors_are_reported() {
+    let body = r#"{"error":"database not found: \"nope\""}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("404 Not Found", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::new(core.handle(), &base_url, "nope").unwrap();
+
+    match core.run(db.add_data("cpu value=1")) {
+        Err(Error::BadRequest(ref message)) if message == r#"database not found: "nope""# => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(support::header(&server.join().unwrap(), "authorization").is_none());
+}
+
+#[test]
+fn other_errors_are_reported_as_they_are() {
+    let (base_url, server) = support::serve_tcp(&support::response("502 Bad Gateway", "text/plain", "upstream is down"));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+
+    match core.run(db.add_data("cpu value=1")) {
+        Err(Error::BadRequest(ref message)) if message == "upstream is down" => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn flux_queries_are_parsed_as_they_stream() {
+    let csv = "\
+#datatype,string,long,dateTime:RFC3339,double,string\r
+#group,false,false,false,false,true\r
+#default,_result,,,,\r
+,result,table,_time,_value,host\r
+,,0,2015-06-11T20:46:02Z,0.64,server01\r
+,,0,2015-06-11T20:46:03Z,,server01\r
+\r
+";
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "text/csv; charset=utf-8", csv));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let query = FluxQuery::new("from(bucket: params.bucket) |> range(start: -1h)")
+        .param("bucket", "my_bucket")
+        .now(UNIX_EPOCH + Duration::from_secs(1434055565));
+    let records = core.run(db.flux_query(&query).collect()).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/query?org=my_org HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(support::header(&request, "authorization"), Some("Token secret"));
+    assert_eq!(support::header(&request, "content-type"), Some("application/json"));
+    assert!(request.contains(r#""now":"2015-06-11T20:46:05Z""#), "{}", request);
+    assert!(request.contains(r#""params":{"bucket":"my_bucket"}"#), "{}", request);
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].result(), Some("_result"));
+    assert_eq!(records[0].get("_time"), Some(&FluxValue::DateTime(UNIX_EPOCH + Duration::from_secs(1434055562))));
+    assert_eq!(records[0].get("_value"), Some(&FluxValue::Double(0.64)));
+    assert_eq!(records[1].get("_value"), Some(&FluxValue::Null));
+    assert_eq!(records[1].get("host").and_then(FluxValue::as_str), Some("server01"));
+}
+
+#[test]
+fn flux_errors_are_reported() {
+    let body = r#"{"code":"invalid","message":"compilation failed: error at @1:1-1:4: undefined identifier nope"}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("400 Bad Request", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+
+    match core.run(db.flux_query(&FluxQuery::new("nope")).collect()) {
+        Err(Error::BadRequest(ref message)) if message.starts_with("compilation failed") => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn buckets_are_created_with_retention_rules() {
+    let body = r#"{"id":"b1","orgID":"o1","name":"devices","type":"user","retentionRules":[{"type":"expire","everySeconds":86400}],"createdAt":"2022-01-01T00:00:00Z"}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("201 Created", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let new_bucket = NewBucket::new("o1", "devices").retention(Duration::from_secs(86400));
+    let bucket = core.run(db.create_bucket(&new_bucket)).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/buckets HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(support::header(&request, "authorization"), Some("Token secret"));
+    assert!(request.contains(r#"{"orgID":"o1","name":"devices","retentionRules":[{"type":"expire","everySeconds":86400}]}"#),
+            "{}", request);
+
+    assert_eq!(bucket.id, "b1");
+    assert_eq!(bucket.org_id, "o1");
+    assert_eq!(bucket.description, None);
+    assert_eq!(bucket.retention_rules, vec![RetentionRule::expire(Duration::from_secs(86400))]);
+}
+
+#[test]
+fn buckets_are_listed_a_page_at_a_time() {
+    let body = r#"{"links":{"self":"/api/v2/buckets?limit=2&offset=2"},"buckets":[{"id":"b1","orgID":"o1","name":"one"},{"id":"b2","orgID":"o1","name":"two","description":"second"}]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my org", "my_bucket", "secret").unwrap();
+    let buckets = core.run(db.buckets(Page::first(2).next())).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /api/v2/buckets?offset=2&limit=2&org=my+org HTTP/1.1\r\n"), "{}", request);
+
+    let names: Vec<_> = buckets.iter().map(|b| b.name.as_str()).collect();
+    assert_eq!(names, ["one", "two"]);
+    assert_eq!(buckets[1].description, Some(String::from("second")));
+}
+
+#[test]
+fn buckets_are_deleted_by_id() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.delete_bucket("b1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("DELETE /api/v2/buckets/b1 HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn bucket_ids_are_percent_encoded() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.delete_bucket("../b 1?x")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("DELETE /api/v2/buckets/..%2Fb%201%3Fx HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn org_ids_are_looked_up_by_name() {
+    let body = r#"{"orgs":[{"id":"o1","name":"my org"}]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my org", "my_bucket", "secret").unwrap();
+    assert_eq!(core.run(db.org_id("my org")).unwrap(), "o1");
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /api/v2/orgs?org=my+org HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn missing_orgs_are_reported() {
+    let body = r#"{"code":"not found","message":"organization name \"nope\" not found"}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("404 Not Found", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+
+    match core.run(db.org_id("nope")) {
+        Err(Error::BadRequest(ref message)) if message == r#"organization name "nope" not found"# => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn orgs_missing_from_the_list_are_not_found() {
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", r#"{"orgs":[]}"#));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+
+    match core.run(db.org_id("nope")) {
+        Err(Error::OrgNotFound(ref name)) if name == "nope" => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn tokens_are_created_for_a_bucket() {
+    let body = r#"{"id":"a1","token":"very secret","status":"active","description":"device 7","orgID":"o1","permissions":[{"action":"write","resource":{"type":"buckets","id":"b1","orgID":"o1","name":"devices","org":"my_org"}}]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("201 Created", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let new_authorization = NewAuthorization::new("o1")
+        .description("device 7")
+        .permission(Permission::write_bucket("o1", "b1"));
+    let authorization = core.run(db.create_authorization(&new_authorization)).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/authorizations HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains(r#"{"orgID":"o1","description":"device 7","permissions":[{"action":"write","resource":{"type":"buckets","id":"b1","orgID":"o1"}}]}"#),
+            "{}", request);
+
+    assert_eq!(authorization.token.as_ref().map(|token| token.secret()), Some("very secret"));
+    assert_eq!(authorization.status, Status::Active);
+    assert_eq!(authorization.permissions[0].action, Action::Write);
+    assert_eq!(authorization.permissions[0].resource.name, Some(String::from("devices")));
+
+    let debug = format!("{:?}", authorization);
+    assert!(!debug.contains("very secret"), "{}", debug);
+}
+
+#[test]
+fn tokens_are_listed() {
+    let body = r#"{"authorizations":[{"id":"a1","token":"very secret","status":"inactive","orgID":"o1","permissions":[]},
+                                     {"id":"a2","status":"active","orgID":"o1","permissions":[]}]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let authorizations = core.run(db.authorizations()).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /api/v2/authorizations?org=my_org HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(authorizations.len(), 2);
+    assert_eq!(authorizations[0].status, Status::Inactive);
+    assert_eq!(authorizations[0].description, None);
+    assert!(authorizations[0].token.is_some());
+    // Newer servers leave out the secret of existing tokens
+    assert_eq!(authorizations[1].token, None);
+}
+
+#[test]
+fn tokens_are_deactivated() {
+    let body = r#"{"id":"a1","token":"very secret","status":"inactive","orgID":"o1","permissions":[]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let authorization = core.run(db.set_authorization_status("a1", Status::Inactive)).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("PATCH /api/v2/authorizations/a1 HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains(r#"{"status":"inactive"}"#), "{}", request);
+    assert_eq!(authorization.status, Status::Inactive);
+}
+
+#[test]
+fn tokens_are_deleted() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.delete_authorization("a1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("DELETE /api/v2/authorizations/a1 HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn token_ids_are_percent_encoded() {
+    let body = r#"{"id":"a/1","token":"very secret","status":"active","orgID":"o1","permissions":[]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.set_authorization_status("a/1", Status::Active)).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("PATCH /api/v2/authorizations/a%2F1 HTTP/1.1\r\n"), "{}", request);
+
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.delete_authorization("a/1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("DELETE /api/v2/authorizations/a%2F1 HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn points_are_deleted_by_range_and_predicate() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let predicate = Predicate::new().measurement("cpu").tag("host", "server01");
+    let start = UNIX_EPOCH;
+    let stop = UNIX_EPOCH + Duration::from_secs(1434055562);
+    core.run(db.delete_range(start, stop, &predicate)).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/delete?bucket=my_bucket&org=my_org HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(support::header(&request, "authorization"), Some("Token secret"));
+    assert!(request.contains(r#"{"start":"1970-01-01T00:00:00Z","stop":"2015-06-11T20:46:02Z","predicate":"\"_measurement\" = \"cpu\" AND \"host\" = \"server01\""}"#),
+            "{}", request);
+}
+
+#[test]
+fn dbrp_mappings_are_created() {
+    let body = r#"{"id":"d1","database":"telegraf","retention_policy":"autogen","default":true,"orgID":"o1","bucketID":"b1"}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("201 Created", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let new_mapping = NewDbrpMapping::new("o1", "b1", "telegraf", "autogen").default(true);
+    let mapping = core.run(db.create_dbrp_mapping(&new_mapping)).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/dbrps HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains(r#"{"orgID":"o1","bucketID":"b1","database":"telegraf","retention_policy":"autogen","default":true}"#),
+            "{}", request);
+    assert_eq!(mapping.id, "d1");
+    assert!(mapping.default);
+}
+
+#[test]
+fn dbrp_mappings_are_listed_by_database() {
+    let body = r#"{"content":[{"id":"d1","database":"telegraf","retention_policy":"autogen","default":true,"orgID":"o1","bucketID":"b1","virtual":false}]}"#;
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/json", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    let mappings = core.run(db.dbrp_mappings(Some("telegraf"))).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /api/v2/dbrps?org=my_org&db=telegraf HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(mappings.len(), 1);
+    assert_eq!(mappings[0].bucket_id, "b1");
+}
+
+#[test]
+fn dbrp_mappings_are_deleted() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.delete_dbrp_mapping("d1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("DELETE /api/v2/dbrps/d1?org=my_org HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn dbrp_mapping_ids_are_percent_encoded() {
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::v2(core.handle(), &base_url, "my_org", "my_bucket", "secret").unwrap();
+    core.run(db.delete_dbrp_mapping("d/1?org=other")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("DELETE /api/v2/dbrps/d%2F1%3Forg=other?org=my_org HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn sql_queries_are_gathered_into_series() {
+    let body = "{\"time\":\"2015-06-11T20:46:02\",\"host\":\"server01\",\"usage\":0.64}\n\
+                {\"time\":\"2015-06-11T20:46:03\",\"host\":\"server02\"}\n";
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "application/jsonl", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let mut db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+    db.set_token(Some("secret"));
+    let query = SqlQuery::new("SELECT time, host, usage FROM cpu WHERE usage > $min").param("min", 0.5);
+    let series = core.run(db.sql_query(&query).series()).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v3/query_sql HTTP/1.1\r\n"), "{}", request);
+    assert_eq!(support::header(&request, "authorization"), Some("Token secret"));
+    assert!(request.contains(r#"{"db":"mydb","q":"SELECT time, host, usage FROM cpu WHERE usage > $min","format":"jsonl","params":{"min":0.5}}"#),
+            "{}", request);
+
+    assert_eq!(series.len(), 1);
+    assert_eq!(series[0].columns, ["time", "host", "usage"]);
+    assert_eq!(series[0].values.len(), 2);
+    assert_eq!(series[0].values[1][2], serde_json::Value::Null);
+}
+
+#[test]
+fn sql_queries_can_use_csv() {
+    let body = "host,usage\nserver01,0.64\n";
+    let (base_url, server) = support::serve_tcp(&support::response("200 OK", "text/csv", body));
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+    let query = SqlQuery::new("SELECT host, usage FROM cpu").format(SqlFormat::Csv);
+    let series = core.run(db.sql_query(&query).collect()).unwrap();
+
+    assert!(server.join().unwrap().contains(r#""format":"csv""#));
+    assert_eq!(series[0].columns, ["host", "usage"]);
+    assert_eq!(series[0].values[0], [serde_json::Value::from("server01"), serde_json::Value::from(0.64)]);
+}
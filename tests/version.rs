@@ -0,0 +1,69 @@
+extern crate tokio_core;
+extern crate futures;
+
+extern crate influxdb;
+
+mod support;
+
+use influxdb::{AsyncDb, Error};
+use influxdb::version::{Flavor, WriteApi};
+
+#[test]
+fn versions_are_read_from_ping() {
+    let response = "HTTP/1.1 204 No Content\r\n\
+                    X-Influxdb-Build: OSS\r\n\
+                    X-Influxdb-Version: 1.8.10\r\n\
+                    Content-Length: 0\r\n\r\n";
+    let (base_url, server) = support::serve_tcp(response);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+    let version = core.run(db.detect_version()).unwrap();
+
+    assert!(server.join().unwrap().starts_with("GET /ping HTTP/1.1\r\n"));
+    assert_eq!(version.flavor, Flavor::Oss);
+    assert_eq!(version.version, "1.8.10");
+    assert_eq!(version.write_api(), WriteApi::V1);
+    assert!(!version.capabilities().flux);
+}
+
+#[test]
+fn versions_are_read_from_health_without_ping_headers() {
+    let health = r#"{"name":"influxdb","message":"ready for queries and writes","status":"pass","version":"v2.7.1"}"#;
+    let (base_url, server) = support::serve_tcp_each(&[support::NO_CONTENT,
+                                                       &support::response("200 OK", "application/json", health)]);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let mut db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+    let version = core.run(db.detect_version()).unwrap();
+
+    let requests = server.join().unwrap();
+    assert!(requests[1].starts_with("GET /health HTTP/1.1\r\n"), "{}", requests[1]);
+    assert_eq!(version.flavor, Flavor::Oss);
+    assert_eq!(version.major_minor(), Some((2, 7)));
+    assert_eq!(version.write_api(), WriteApi::V2);
+
+    // Writes then go through the 2.x API
+    let (base_url, server) = support::serve_tcp(support::NO_CONTENT);
+    db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+    db.set_write_api(version.write_api()).unwrap();
+    core.run(db.add_data("cpu value=1")).unwrap();
+
+    let request = server.join().unwrap();
+    assert!(request.starts_with("POST /api/v2/write?bucket=mydb&precision=ns HTTP/1.1\r\n"), "{}", request);
+}
+
+#[test]
+fn servers_that_do_not_say_their_version_are_reported() {
+    let health = r#"{"name":"influxdb","status":"pass"}"#;
+    let (base_url, server) = support::serve_tcp_each(&[support::NO_CONTENT,
+                                                       &support::response("200 OK", "application/json", health)]);
+
+    let mut core = tokio_core::reactor::Core::new().unwrap();
+    let db = AsyncDb::new(core.handle(), &base_url, "mydb").unwrap();
+    match core.run(db.detect_version()) {
+        Err(Error::UnknownVersion) => {}
+        other => panic!("expected the version to be unknown, got {:?}", other),
+    }
+    server.join().unwrap();
+}